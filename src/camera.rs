@@ -6,11 +6,18 @@ pub struct Camera {
     pub near: f32,
     pub far: f32,
     pub fov: f32,
+    pub aspect: f32,
     pub rot_x: f32,
     pub rot_y: f32,
     pub rot_z: f32,
 }
 
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new()
+    }
+}
+
 impl Camera {
     pub fn new() -> Camera {
         Camera {
@@ -21,6 +28,7 @@ impl Camera {
             near        : 0.1,
             far         : 20.,
             fov         : 90.,
+            aspect      : 1.,
         }
     }
 
@@ -41,10 +49,16 @@ impl Camera {
     }
 
     pub fn get_direction(&self) -> Vector3 {
-        let (v,w) = &self.get_mat() * Vector3::new(0.,0.,1.);
+        let (v, _) = &self.get_mat() * Vector3::new(0.,0.,1.);
         v.normalize()
     }
 
+    // Eye point in world space, the point the view transform maps to the origin.
+    pub fn position(&self) -> Vector3 {
+        let rot = Matrix4::rot(self.rot_x, self.rot_y, self.rot_z);
+        -rot.transpose().transform_dir(self.translation)
+    }
+
     pub fn get_mat(&self) -> Matrix4 {
         Matrix4::rot_and_translate(self.rot_x, self.rot_y, self.rot_z, self.translation)
     }
@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use rusterizer::renderer::Settings;
+use rusterizer::scene::Scene;
+use rusterizer::color::Color;
+use rusterizer::light::parse_vec;
+use rusterizer::texture::{Filter, Wrap};
+use rusterizer::mesh::DEFAULT_CREASE_ANGLE_DEGREES;
+
+pub enum Command {
+    // Open the interactive viewer on a mesh.
+    View { mesh_file: String, crease_angle: f32 },
+    // Render a single frame to an image file, without any window.
+    Render(RenderOptions),
+    // Write a mesh to another file format.
+    Convert { mesh_file: String, output: String, ascii: bool },
+}
+
+pub struct RenderOptions {
+    pub mesh_file: String,
+    pub output: String,
+    pub width: usize,
+    pub height: usize,
+    pub settings: Settings,
+    pub scene: Scene,
+    // Image applied to the mesh material, for meshes with texture coordinates.
+    pub texture: Option<String>,
+    // Override the sampling of every texture.
+    pub filter: Option<Filter>,
+    pub wrap: Option<Wrap>,
+    // In degrees, see Mesh::corner_normals.
+    pub crease_angle: f32,
+}
+
+pub const USAGE: &str = "\
+usage: rusterizer <mesh.off|mesh.obj|mesh.stl|mesh.ply|mesh.gltf|mesh.glb> [--crease <degrees>]
+       rusterizer render <mesh.off|mesh.obj|mesh.stl|mesh.ply|mesh.gltf|mesh.glb> [-o <out.png|out.ppm>] [--width <px>] [--height <px>]
+                         [--mode <normal|shaded|gouraud|phong|color|wireframe|pbr>]
+                         [--crease <degrees>] [--transparency <sorted|oit>]
+                         [--no-shadows] [--shadow-bias <depth>] [--shadow-size <px>]
+                         [--pcf <radius>] [--light <spec>]... [--ambient <r,g,b[:intensity]>]
+                         [--texture <image>] [--filter <nearest|bilinear|trilinear>]
+                         [--wrap <repeat|clamp|mirror>] [--threads <n>]
+       rusterizer convert <mesh> <out.off|out.stl|out.ply> [--ascii]
+
+A light is given as one of, with colors in [0, 1] and the angle in degrees:
+  directional:DX,DY,DZ[:R,G,B[:INTENSITY]]
+  point:X,Y,Z[:R,G,B[:INTENSITY]]
+  spot:X,Y,Z:DX,DY,DZ:ANGLE[:R,G,B[:INTENSITY]]
+Giving any --light replaces the default white directional light.
+The image is rendered by one thread per core unless --threads is given.
+
+Meshes can be gzip compressed. The format is chosen from the extension, or
+from the content when it is unknown. A mesh file of - reads the standard input.
+
+The viewer is only available when built with --features viewer. In it, the
+keys 1 to 7 switch between the render modes, S toggles the shadows, T switches
+between sorted and order-independent transparency and P saves the frame to
+screenshot.png.";
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    match args.first().map(|s| s.as_str()) {
+        None => Err("expected a mesh file path as argument".to_string()),
+        Some("render") => parse_render(&args[1..]).map(Command::Render),
+        Some("convert") => parse_convert(&args[1..]),
+        Some(_) => parse_view(args),
+    }
+}
+
+fn parse_view(args: &[String]) -> Result<Command, String> {
+    let mesh_file = args[0].clone();
+    let mut crease_angle = DEFAULT_CREASE_ANGLE_DEGREES;
+
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next()
+            .ok_or_else(|| format!("missing value for [{}]", arg));
+
+        match arg.as_str() {
+            "--crease" => crease_angle = parse_angle(value()?)?,
+            _ => return Err(format!("unexpected argument [{}]", arg)),
+        }
+    }
+
+    Ok(Command::View { mesh_file, crease_angle })
+}
+
+fn parse_convert(args: &[String]) -> Result<Command, String> {
+    let mut files = vec!();
+    let mut ascii = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--ascii" => ascii = true,
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option [{}]", arg)),
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    match files.as_slice() {
+        [mesh_file, output] => Ok(Command::Convert {
+            mesh_file: mesh_file.clone(),
+            output: output.clone(),
+            ascii,
+        }),
+        _ => Err("expected an input and an output mesh file path".to_string()),
+    }
+}
+
+fn parse_render(args: &[String]) -> Result<RenderOptions, String> {
+    let mut mesh_file = None;
+    let mut output = None;
+    let mut width = 800;
+    let mut height = 600;
+    let mut settings = Settings::default();
+    let mut crease_angle = DEFAULT_CREASE_ANGLE_DEGREES;
+    let mut scene = Scene::default();
+    let mut lights = vec!();
+    let mut texture = None;
+    let mut filter = None;
+    let mut wrap = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next()
+            .ok_or_else(|| format!("missing value for [{}]", arg));
+
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(value()?.clone()),
+            "--width" => width = parse_size(value()?)?,
+            "--height" => height = parse_size(value()?)?,
+            "--mode" => settings.mode = value()?.parse()?,
+            "--transparency" => settings.transparency = value()?.parse()?,
+            "--no-shadows" => settings.shadow.enabled = false,
+            "--shadow-bias" => {
+                let v = value()?;
+                settings.shadow.bias = v.parse().map_err(|_| format!("invalid shadow bias [{}]", v))?;
+            },
+            "--shadow-size" => settings.shadow.size = parse_size(value()?)?,
+            "--pcf" => {
+                let v = value()?;
+                settings.shadow.pcf_radius = v.parse().map_err(|_| format!("invalid filter radius [{}]", v))?;
+            },
+            "--crease" => crease_angle = parse_angle(value()?)?,
+            "--light" => lights.push(value()?.parse()?),
+            "--ambient" => {
+                let (color, intensity) = parse_ambient(value()?)?;
+                scene.ambient = color;
+                scene.ambient_intensity = intensity;
+            },
+            "--texture" => texture = Some(value()?.clone()),
+            "--filter" => filter = Some(value()?.parse()?),
+            "--wrap" => wrap = Some(value()?.parse()?),
+            "--threads" => settings.threads = parse_threads(value()?)?,
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option [{}]", arg)),
+            _ if mesh_file.is_none() => mesh_file = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument [{}]", arg)),
+        }
+    }
+
+    let mesh_file = mesh_file.ok_or_else(|| "expected a mesh file path".to_string())?;
+    if !lights.is_empty() {
+        scene.lights = lights;
+    }
+
+    // Default to a PNG next to the mesh file, replacing its extension and a
+    // .gz suffix.
+    let output = output.unwrap_or_else(|| {
+        if mesh_file == "-" {
+            return "out.png".to_string();
+        }
+        let path = Path::new(&mesh_file);
+        let path = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gz") => path.with_extension(""),
+            _ => path.to_path_buf(),
+        };
+        path.with_extension("png").to_string_lossy().into_owned()
+    });
+
+    Ok(RenderOptions { mesh_file, output, width, height, settings, scene, texture, filter, wrap,
+        crease_angle })
+}
+
+fn parse_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid image size [{}]", s)),
+    }
+}
+
+fn parse_threads(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid thread count [{}]", s)),
+    }
+}
+
+fn parse_angle(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(a) if (0. ..= 180.).contains(&a) => Ok(a),
+        _ => Err(format!("invalid angle [{}]", s)),
+    }
+}
+
+// R,G,B[:INTENSITY], the intensity defaults to 1.
+fn parse_ambient(s: &str) -> Result<(Color, f32), String> {
+    let (color, intensity) = match s.split_once(':') {
+        Some((c, i)) => (c, i.parse::<f32>().map_err(|_| format!("invalid intensity [{}]", i))?),
+        None => (s, 1.),
+    };
+    let c = parse_vec(color)?;
+    Ok((Color::from_f32(c.x, c.y, c.z, 1.), intensity))
+}
@@ -0,0 +1,129 @@
+use crate::vector::Vector3;
+use crate::varyings::Varyings;
+
+// A vertex after projection, before the perspective divide.
+#[derive(Copy, Clone)]
+pub struct ClipVertex {
+    pub pos: Vector3,
+    pub w: f32,
+    pub varyings: Varyings,
+}
+
+impl ClipVertex {
+    pub fn new((pos, w): (Vector3, f32), varyings: Varyings) -> ClipVertex {
+        ClipVertex { pos, w, varyings }
+    }
+
+    // Clipping happens before the perspective divide, so attributes are
+    // interpolated linearly here.
+    pub fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            pos: self.pos + (other.pos - self.pos) * t,
+            w: self.w + (other.w - self.w) * t,
+            varyings: self.varyings.lerp(&other.varyings, t),
+        }
+    }
+}
+
+// The planes of the view frustum, in homogeneous clip space: a vertex is
+// inside when -w <= x, y, z <= w.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Plane {
+    Near,
+    Far,
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+// Clipping against the near plane is what keeps points with w <= 0 out of
+// the perspective divide, the far plane bounds the depth range. The side
+// planes are optional but keep the screen coordinates small.
+pub const FRUSTUM: [Plane; 6] = [
+    Plane::Near, Plane::Far, Plane::Left, Plane::Right, Plane::Bottom, Plane::Top
+];
+
+impl Plane {
+    // Signed distance to the plane, positive on the inner side.
+    pub fn distance(&self, v: &ClipVertex) -> f32 {
+        match self {
+            Plane::Near => v.w + v.pos.z,
+            Plane::Far => v.w - v.pos.z,
+            Plane::Left => v.w + v.pos.x,
+            Plane::Right => v.w - v.pos.x,
+            Plane::Bottom => v.w + v.pos.y,
+            Plane::Top => v.w - v.pos.y,
+        }
+    }
+}
+
+// Sutherland–Hodgman polygon clipping against each plane in turn.
+pub fn clip_polygon(polygon: &[ClipVertex], planes: &[Plane]) -> Vec<ClipVertex> {
+    let mut output = polygon.to_vec();
+
+    for plane in planes {
+        if output.is_empty() {
+            break;
+        }
+        let input = std::mem::take(&mut output);
+        let mut prev = &input[input.len() - 1];
+        let mut prev_d = plane.distance(prev);
+
+        for cur in &input {
+            let cur_d = plane.distance(cur);
+            if cur_d >= 0. {
+                if prev_d < 0. {
+                    output.push(prev.lerp(cur, prev_d / (prev_d - cur_d)));
+                }
+                output.push(*cur);
+            } else if prev_d >= 0. {
+                output.push(prev.lerp(cur, prev_d / (prev_d - cur_d)));
+            }
+            prev = cur;
+            prev_d = cur_d;
+        }
+    }
+
+    output
+}
+
+// Clip a triangle and split the resulting convex polygon back into a fan of
+// triangles, keeping the original winding.
+pub fn clip_triangle(tri: [ClipVertex; 3], planes: &[Plane]) -> Vec<[ClipVertex; 3]> {
+    // Fast path: most triangles are entirely inside.
+    if planes.iter().all(|p| tri.iter().all(|v| p.distance(v) >= 0.)) {
+        return vec![tri];
+    }
+
+    let polygon = clip_polygon(&tri, planes);
+    if polygon.len() < 3 {
+        return vec![];
+    }
+    (1 .. polygon.len() - 1)
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+// Clip a segment, returns None if it is entirely outside.
+pub fn clip_line(a: ClipVertex, b: ClipVertex, planes: &[Plane]) -> Option<(ClipVertex, ClipVertex)> {
+    let (mut t0, mut t1) = (0f32, 1f32);
+
+    for plane in planes {
+        let da = plane.distance(&a);
+        let db = plane.distance(&b);
+        if da < 0. && db < 0. {
+            return None;
+        }
+        if da < 0. {
+            t0 = t0.max(da / (da - db));
+        } else if db < 0. {
+            t1 = t1.min(da / (da - db));
+        }
+        if t0 > t1 {
+            return None;
+        }
+    }
+
+    Some((a.lerp(&b, t0), a.lerp(&b, t1)))
+}
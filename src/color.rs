@@ -0,0 +1,69 @@
+use crate::vector::Vector3;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color { r: 0, g: 0, b: 0, a: 255 };
+    pub const WHITE: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+    pub fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    // From components in the [0, 1] range, clamped.
+    pub fn from_f32(r: f32, g: f32, b: f32, a: f32) -> Color {
+        fn to_u8(v: f32) -> u8 {
+            (v.clamp(0., 1.) * 255.).round() as u8
+        }
+        Color { r: to_u8(r), g: to_u8(g), b: to_u8(b), a: to_u8(a) }
+    }
+
+    // RGB components in the [0, 1] range.
+    pub fn to_vec(self) -> Vector3 {
+        Vector3::new(self.r as f32, self.g as f32, self.b as f32) / 255.
+    }
+
+    pub fn from_vec(v: Vector3) -> Color {
+        Color::from_f32(v.x, v.y, v.z, 1.)
+    }
+
+    // RGB components decoded from sRGB to linear light.
+    pub fn to_linear(self) -> Vector3 {
+        fn decode(c: u8) -> f32 {
+            let c = c as f32 / 255.;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        }
+        Vector3::new(decode(self.r), decode(self.g), decode(self.b))
+    }
+
+    // Opaque color from linear light RGB components, encoded to sRGB.
+    pub fn from_linear(v: Vector3) -> Color {
+        fn encode(c: f32) -> f32 {
+            let c = c.clamp(0., 1.);
+            if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1. / 2.4) - 0.055 }
+        }
+        Color::from_f32(encode(v.x), encode(v.y), encode(v.z), 1.)
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.a == 255
+    }
+
+    // Composite this color over another one, according to its alpha.
+    pub fn over(&self, dst: Color) -> Color {
+        let a = self.a as f32 / 255.;
+        let dst_a = dst.a as f32 / 255.;
+        let c = self.to_vec() * a + dst.to_vec() * (1. - a);
+        Color::from_f32(c.x, c.y, c.z, a + dst_a * (1. - a))
+    }
+}
@@ -0,0 +1,114 @@
+use crate::color::Color;
+use crate::vector::Vector3;
+
+// How the fragments of a triangle are written to the framebuffer. In all
+// cases the fragments are depth tested.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Blend {
+    // The color replaces the pixel and the depth is written.
+    Opaque,
+    // The color is composited over the pixel, the depth is not written.
+    // Triangles must be drawn back to front.
+    Alpha,
+    // Weighted blended order-independent transparency: the color is
+    // accumulated in separate buffers, composited by resolve_oit.
+    WeightedOit,
+}
+
+// Software render target: every render mode writes its pixels here, and the
+// result can then be presented in a window or saved to disk.
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub color: Vec<Color>,
+    pub depth: Vec<f32>,
+    // Order-independent transparency buffers, allocated by clear_oit.
+    pub accum: Vec<[f32; 4]>,
+    pub revealage: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            color: vec![Color::BLACK; width * height],
+            depth: vec![f32::MAX; width * height],
+            accum: Vec::new(),
+            revealage: Vec::new(),
+        }
+    }
+
+    // Fill the color buffer and reset the depth buffer.
+    pub fn clear(&mut self, color: Color) {
+        for c in self.color.iter_mut() {
+            *c = color;
+        }
+        for z in self.depth.iter_mut() {
+            *z = f32::MAX;
+        }
+    }
+
+    pub fn clear_oit(&mut self) {
+        let size = self.width * self.height;
+        self.accum.clear();
+        self.accum.resize(size, [0.; 4]);
+        self.revealage.clear();
+        self.revealage.resize(size, 1.);
+    }
+
+    // Composite the accumulated transparent fragments over the color buffer.
+    pub fn resolve_oit(&mut self) {
+        for (off, c) in self.color.iter_mut().enumerate() {
+            let revealage = self.revealage[off];
+            if revealage >= 1. {
+                continue;
+            }
+            let [r, g, b, a] = self.accum[off];
+            let a = a.max(1e-5);
+            let dst = c.to_vec();
+            let src = Vector3::new(r / a, g / a, b / a);
+            *c = Color::from_vec(src * (1. - revealage) + dst * revealage);
+        }
+    }
+
+    // Write a fragment which passed the depth test at the given offset.
+    pub fn write(&mut self, off: usize, z: f32, color: Color, blend: Blend) {
+        match blend {
+            Blend::Opaque => {
+                self.color[off] = color;
+                self.depth[off] = z;
+            },
+            Blend::Alpha => {
+                self.color[off] = color.over(self.color[off]);
+            },
+            Blend::WeightedOit => {
+                // Weight function from McGuire and Bavoil, "Weighted Blended
+                // Order-Independent Transparency", with the depth in [0, 1].
+                let a = color.a as f32 / 255.;
+                let d = (z + 1.) / 2.;
+                let w = a * (3e3 * (1. - d).powi(3)).clamp(1e-2, 3e3);
+                let c = color.to_vec() * (a * w);
+                let accum = &mut self.accum[off];
+                accum[0] += c.x;
+                accum[1] += c.y;
+                accum[2] += c.z;
+                accum[3] += a * w;
+                self.revealage[off] *= 1. - a;
+            },
+        }
+    }
+
+    pub fn offset(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.color[self.offset(x, y)]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        let off = self.offset(x, y);
+        self.color[off] = color;
+    }
+}
@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// Where an error was found in the file.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Location {
+    // Line and column of text files, both starting at 1.
+    Text { line: usize, column: usize },
+    // Offset of binary data.
+    Byte(usize),
+    EndOfFile,
+    // The error is not tied to a place in the file, like a missing element
+    // or a reference between two parts of it.
+    Unknown,
+}
+
+impl Location {
+    // Location of a token, which must be a slice of the text starting at
+    // `line`. The text can span several lines, like the continued lines of
+    // OBJ files.
+    pub fn token(line: usize, text: &str, token: &str) -> Location {
+        let offset = (token.as_ptr() as usize).saturating_sub(text.as_ptr() as usize);
+        let before = &text[.. offset.min(text.len())];
+        let start = before.rfind('\n').map_or(0, |i| i + 1);
+        Location::Text {
+            line: line + before.matches('\n').count(),
+            column: before[start ..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Text { line, column } => write!(f, " at line {}, column {}", line, column),
+            Location::Byte(offset) => write!(f, " at byte {}", offset),
+            Location::EndOfFile => write!(f, " at end of file"),
+            Location::Unknown => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    // The file is not in the expected format, or in a variant of it which is
+    // not recognized.
+    BadHeader { location: Location, token: String },
+    // Fewer elements or values than required, `what` names them.
+    CountMismatch { location: Location, what: &'static str, expected: usize, found: usize },
+    IndexOutOfRange { location: Location, index: i64, count: usize },
+    // A token which is not what the format requires at this place.
+    Parse { location: Location, token: String, expected: &'static str },
+    // Valid data which can not be loaded.
+    Unsupported { location: Location, what: String },
+    // A reference to an element which is not defined, like a material.
+    UnknownName { location: Location, what: &'static str, name: String },
+    Gltf(::gltf::Error),
+}
+
+impl ImportError {
+    pub fn parse(location: Location, token: &str, expected: &'static str) -> ImportError {
+        ImportError::Parse { location, token: token.to_string(), expected }
+    }
+}
+
+// Parse a token of a text file, which must be a slice of the line.
+pub fn parse_token<T: FromStr>(line: usize, text: &str, token: &str, expected: &'static str) -> Result<T, ImportError> {
+    token.parse()
+        .map_err(|_| ImportError::parse(Location::token(line, text, token), token, expected))
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::BadHeader { location, token } =>
+                write!(f, "unrecognized format [{}]{}", token, location),
+            ImportError::CountMismatch { location, what, expected, found } =>
+                write!(f, "expected {} {}, found {}{}", expected, what, found, location),
+            ImportError::IndexOutOfRange { location, index, count } =>
+                write!(f, "index [{}] out of range for {} elements{}", index, count, location),
+            ImportError::Parse { location, token, expected } if token.is_empty() =>
+                write!(f, "missing {}{}", expected, location),
+            ImportError::Parse { location, token, expected } =>
+                write!(f, "invalid {} [{}]{}", expected, token, location),
+            ImportError::Unsupported { location, what } =>
+                write!(f, "unsupported {}{}", what, location),
+            ImportError::UnknownName { location, what, name } =>
+                write!(f, "unknown {} [{}]{}", what, name, location),
+            ImportError::Gltf(e) => write!(f, "invalid glTF file: {}", e),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImportError::Io(e) => Some(e),
+            ImportError::Gltf(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Problem which did not stop the import, the data concerned being left out
+// of the mesh. `file` is set when the problem is in a file referenced by the
+// one imported, like a material library or a texture.
+#[derive(Debug)]
+pub struct ImportWarning {
+    pub file: Option<PathBuf>,
+    pub error: ImportError,
+}
+
+impl fmt::Display for ImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} in [{}]", self.error, file.display()),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> ImportError {
+        ImportError::Io(e)
+    }
+}
+
+impl From<::gltf::Error> for ImportError {
+    fn from(e: ::gltf::Error) -> ImportError {
+        match e {
+            ::gltf::Error::Io(e) => ImportError::Io(e),
+            e => ImportError::Gltf(e),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+
+use flate2::read::MultiGzDecoder;
+
+use crate::mesh::Mesh;
+use crate::io::error::{ImportError, Location};
+use crate::io::{off, obj, stl, ply, gltf};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    Off,
+    Obj,
+    Stl,
+    Ply,
+    // JSON or binary glTF.
+    Gltf,
+    // Detected from the content.
+    Auto,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "off" => Ok(Format::Off),
+            "obj" => Ok(Format::Obj),
+            "stl" => Ok(Format::Stl),
+            "ply" => Ok(Format::Ply),
+            "gltf" | "glb" => Ok(Format::Gltf),
+            "auto" => Ok(Format::Auto),
+            _ => Err(format!("unknown mesh format [{}]", s)),
+        }
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+impl Format {
+    // Format given by the extension of a file, ignoring a .gz suffix. Auto
+    // when the extension is unknown.
+    pub fn from_path(path: &Path) -> Format {
+        let path = match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gz") => path.file_stem().map(Path::new).unwrap_or(path),
+            _ => path,
+        };
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.to_ascii_lowercase().parse().ok())
+            .unwrap_or(Format::Auto)
+    }
+
+    // Recognize the format from the start of the data, None if it matches
+    // none of them.
+    pub fn detect(data: &[u8]) -> Option<Format> {
+        if data.starts_with(b"glTF") {
+            return Some(Format::Gltf);
+        }
+        if data.starts_with(b"ply") {
+            return Some(Format::Ply);
+        }
+        // Binary STL files have a free header, only their size tells them apart.
+        if let Some(n) = data.get(80 .. 84) {
+            let count = u32::from_le_bytes([n[0], n[1], n[2], n[3]]) as usize;
+            if data.len() == 84 + 50 * count {
+                return Some(Format::Stl);
+            }
+        }
+
+        // Text formats, from the first line which is not blank or a comment.
+        let head = String::from_utf8_lossy(&data[.. data.len().min(4096)]);
+        if head.trim_start().starts_with('{') {
+            return Some(Format::Gltf);
+        }
+        let first = head.lines()
+            .map(|l| l.trim())
+            .find(|l| !l.is_empty() && !l.starts_with('#'))?;
+        let token = first.split_whitespace().next()?;
+        match token {
+            t if t.ends_with("OFF") => Some(Format::Off),
+            "solid" => Some(Format::Stl),
+            "v" | "vt" | "vn" | "f" | "o" | "g" | "s" | "mtllib" | "usemtl" => Some(Format::Obj),
+            _ => None,
+        }
+    }
+}
+
+// Read a mesh in the given format, decompressing gzip data first, which can
+// be made of several concatenated members. Files
+// referenced by the mesh, like OBJ material libraries and glTF buffers, are
+// looked up in `dir`.
+pub fn import<R: Read>(r: &mut R, format: Format, dir: &Path) -> Result<Mesh, ImportError> {
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+    if data.starts_with(GZIP_MAGIC) {
+        let mut decoded = vec!();
+        MultiGzDecoder::new(&data[..]).read_to_end(&mut decoded)?;
+        data = decoded;
+    }
+
+    let format = match format {
+        Format::Auto => Format::detect(&data).ok_or_else(|| ImportError::Unsupported {
+            location: Location::Unknown,
+            what: "mesh format".to_string(),
+        })?,
+        format => format,
+    };
+
+    let mut data = &data[..];
+    match format {
+        // Auto was resolved above.
+        Format::Off | Format::Auto => off::import(&mut data),
+        Format::Obj => obj::import(&mut data, dir),
+        Format::Stl => stl::import(&mut data),
+        Format::Ply => ply::import(&mut data),
+        Format::Gltf => gltf::import(&mut data, dir),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    const CUBE: &str = "OFF\n4 2 0\n0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 2\n3 0 2 3\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(vec!(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(Format::from_path(Path::new("dir.v2/mesh.OBJ")), Format::Obj);
+        assert_eq!(Format::from_path(Path::new("mesh.off.gz")), Format::Off);
+        assert_eq!(Format::from_path(Path::new("mesh.glb.GZ")), Format::Gltf);
+        assert_eq!(Format::from_path(Path::new("mesh.gz")), Format::Auto);
+        assert_eq!(Format::from_path(Path::new("mesh")), Format::Auto);
+    }
+
+    #[test]
+    fn detect_format() {
+        let mut binary_stl = vec![0; 84 + 50];
+        binary_stl[.. 5].copy_from_slice(b"solid");
+        binary_stl[80] = 1;
+
+        assert_eq!(Format::detect(b"glTF\x02\0\0\0"), Some(Format::Gltf));
+        assert_eq!(Format::detect(b"  {\"asset\": {}}"), Some(Format::Gltf));
+        assert_eq!(Format::detect(b"ply\nformat ascii 1.0\n"), Some(Format::Ply));
+        assert_eq!(Format::detect(&binary_stl), Some(Format::Stl));
+        assert_eq!(Format::detect(b"solid cube\nendsolid cube\n"), Some(Format::Stl));
+        assert_eq!(Format::detect(b"# comment\n\nNOFF\n"), Some(Format::Off));
+        assert_eq!(Format::detect(b"# exported\nmtllib a.mtl\nv 0 0 0\n"), Some(Format::Obj));
+        assert_eq!(Format::detect(b"hello"), None);
+        assert_eq!(Format::detect(b""), None);
+    }
+
+    #[test]
+    fn gzip_members() {
+        let mesh = import(&mut &gzip(CUBE.as_bytes())[..], Format::Auto, Path::new("")).unwrap();
+        assert_eq!((mesh.vertices.len(), mesh.faces.len()), (4, 2));
+
+        // The file split in two members, concatenated.
+        let (head, tail) = CUBE.split_at(20);
+        let mut data = gzip(head.as_bytes());
+        data.extend(gzip(tail.as_bytes()));
+        let mesh = import(&mut &data[..], Format::Off, Path::new("")).unwrap();
+        assert_eq!((mesh.vertices.len(), mesh.faces.len()), (4, 2));
+    }
+}
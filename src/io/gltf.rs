@@ -0,0 +1,383 @@
+use std::io::prelude::*;
+use std::fs;
+use std::path::Path;
+
+use ::gltf::{Gltf, Node, Primitive};
+use ::gltf::buffer;
+use ::gltf::image;
+use ::gltf::material::AlphaMode;
+use ::gltf::mesh::Mode;
+use ::gltf::texture::{MinFilter, WrappingMode};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use crate::color::Color;
+use crate::vector::Vector3;
+use crate::matrix::Matrix4;
+use crate::material::Material;
+use crate::texture::{self, Texture};
+use crate::io::image::read_png;
+use crate::mesh::*;
+use crate::io::error::{ImportError, ImportWarning, Location};
+
+// Path of a relative URI, whose escaped characters like %20 or %C3%A9 are
+// decoded.
+fn uri_path(uri: &str) -> Result<String, ImportError> {
+    let invalid = || ImportError::parse(Location::Unknown, uri, "URI");
+    let digit = |c: &u8| (*c as char).to_digit(16);
+    let mut bytes = vec!();
+    let mut rest = uri.as_bytes();
+    while let Some((&c, tail)) = rest.split_first() {
+        rest = tail;
+        if c != b'%' {
+            bytes.push(c);
+            continue;
+        }
+        match (tail.first().and_then(digit), tail.get(1).and_then(digit)) {
+            (Some(high), Some(low)) => bytes.push((high * 16 + low) as u8),
+            _ => return Err(invalid()),
+        }
+        rest = &tail[2 ..];
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+// Content of a URI, either embedded as base64 in a data URI or in a file
+// relative to the glTF file.
+fn read_uri(uri: &str, dir: &Path) -> Result<Vec<u8>, ImportError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,").ok_or_else(|| ImportError::Unsupported {
+            location: Location::Unknown,
+            what: "data URI encoding".to_string(),
+        })?;
+        return BASE64.decode(encoded)
+            .map_err(|_| ImportError::parse(Location::Unknown, "data URI", "base64 data"));
+    }
+
+    Ok(fs::read(dir.join(uri_path(uri)?))?)
+}
+
+fn load_buffers(gltf: &Gltf, dir: &Path) -> Result<Vec<Vec<u8>>, ImportError> {
+    gltf.buffers()
+        .map(|b| match b.source() {
+            buffer::Source::Bin => gltf.blob.clone()
+                .ok_or_else(|| ImportError::parse(Location::Unknown, "", "binary chunk")),
+            buffer::Source::Uri(uri) => read_uri(uri, dir),
+        })
+        .collect()
+}
+
+// Decode an image, only PNG is supported. JPEG images, also allowed by the
+// specification, are reported as unsupported.
+fn load_image(image: image::Image, buffers: &[Vec<u8>], dir: &Path) -> Result<Texture, ImportError> {
+    let bytes = match image.source() {
+        image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            buffer.get(view.offset() .. view.offset() + view.length())
+                .ok_or_else(|| ImportError::parse(Location::Unknown, &image.index().to_string(), "image buffer view"))?
+                .to_vec()
+        },
+        image::Source::Uri { uri, .. } => read_uri(uri, dir)?,
+    };
+    if !bytes.starts_with(b"\x89PNG") {
+        let format = if bytes.starts_with(b"\xff\xd8\xff") { "JPEG" } else { "format of" };
+        return Err(ImportError::Unsupported {
+            location: Location::Unknown,
+            what: format!("{} image [{}]", format, image.index()),
+        });
+    }
+    match read_png(&bytes[..]) {
+        Ok(img) if img.width > 0 && img.height > 0 => Ok(Texture::new(img.width, img.height, &img.pixels)),
+        _ => Err(ImportError::parse(Location::Unknown, &image.index().to_string(), "PNG image")),
+    }
+}
+
+fn wrap(mode: WrappingMode) -> texture::Wrap {
+    match mode {
+        WrappingMode::ClampToEdge => texture::Wrap::Clamp,
+        WrappingMode::MirroredRepeat => texture::Wrap::Mirror,
+        WrappingMode::Repeat => texture::Wrap::Repeat,
+    }
+}
+
+// Colors are given in linear light, the materials store them in sRGB.
+fn srgb(c: [f32; 3]) -> Color {
+    Color::from_linear(Vector3::new(c[0], c[1], c[2]))
+}
+
+// Load the textures and the metallic/roughness materials into the mesh. The
+// mesh materials have the same indices as in the file. The images which can
+// not be loaded are reported as warnings, and the materials using them are
+// left without a texture.
+fn load_materials(gltf: &Gltf, buffers: &[Vec<u8>], dir: &Path, mesh: &mut Mesh) {
+    let mut images: Vec<Option<Texture>> = gltf.images()
+        .map(|image| {
+            let file = match image.source() {
+                image::Source::Uri { uri, .. } if !uri.starts_with("data:") =>
+                    uri_path(uri).ok().map(|path| dir.join(path)),
+                _ => None,
+            };
+            load_image(image, buffers, dir)
+                .map_err(|error| mesh.warnings.push(ImportWarning { file, error }))
+                .ok()
+        })
+        .collect();
+    let loaded: Vec<bool> = images.iter().map(|i| i.is_some()).collect();
+
+    // Each texture gets its own copy of the image, with its sampler settings.
+    let textures: Vec<Option<usize>> = gltf.textures()
+        .map(|t| {
+            if !loaded[t.source().index()] {
+                return None;
+            }
+            let mut texture = match images[t.source().index()].take() {
+                Some(texture) => texture,
+                None => load_image(t.source(), buffers, dir).ok()?,
+            };
+            let sampler = t.sampler();
+            texture.wrap_u = wrap(sampler.wrap_s());
+            texture.wrap_v = wrap(sampler.wrap_t());
+            texture.filter = match sampler.min_filter() {
+                Some(MinFilter::Nearest) => texture::Filter::Nearest,
+                Some(MinFilter::Linear) => texture::Filter::Bilinear,
+                _ => texture::Filter::Trilinear,
+            };
+            mesh.textures.push(texture);
+            Some(mesh.textures.len() - 1)
+        })
+        .collect();
+
+    for m in gltf.materials() {
+        let pbr = m.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        let alpha = match m.alpha_mode() {
+            AlphaMode::Blend => a,
+            _ => 1.,
+        };
+
+        mesh.materials.push(Material {
+            diffuse: Color { a: (alpha.clamp(0., 1.) * 255.).round() as u8, ..srgb([r, g, b]) },
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            emissive: srgb(m.emissive_factor()),
+            texture: pbr.base_color_texture().and_then(|info| textures[info.texture().index()]),
+            ..Material::default()
+        });
+    }
+}
+
+fn node_matrix(node: &Node) -> Matrix4 {
+    // glTF matrices are column major.
+    let m = node.transform().matrix();
+    let mut out = Matrix4::zero();
+    for (c, column) in m.iter().enumerate() {
+        for (r, v) in column.iter().enumerate() {
+            out.cells[r][c] = *v;
+        }
+    }
+    out
+}
+
+// Vertex positions, normals (if any) and triangles of a primitive, in the
+// primitive's own indices.
+type Geometry = (Vec<Vertex>, Option<Vec<Vector3>>, Vec<[usize; 3]>);
+
+fn read_primitive(primitive: &Primitive, buffers: &[Vec<u8>]) -> Result<Geometry, ImportError> {
+    let reader = primitive.reader(|b| buffers.get(b.index()).map(|b| b.as_slice()));
+
+    let positions: Vec<[f32; 3]> = reader.read_positions()
+        .ok_or_else(|| ImportError::parse(Location::Unknown, "", "primitive positions"))?
+        .collect();
+    let normals = reader.read_normals()
+        .map(|n| n.map(|[x, y, z]| Vector3::new(x, y, z)).collect::<Vec<_>>())
+        .filter(|n| n.len() == positions.len());
+    let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|t| t.into_f32().collect());
+    let colors: Option<Vec<[f32; 4]>> = reader.read_colors(0).map(|c| c.into_rgba_f32().collect());
+
+    let vertices = positions.iter().enumerate()
+        .map(|(i, p)| Vertex {
+            pt: Vector3::new(p[0], p[1], p[2]),
+            color: colors.as_ref().and_then(|c| c.get(i)).map(|c| {
+                Color { a: (c[3].clamp(0., 1.) * 255.).round() as u8, ..srgb([c[0], c[1], c[2]]) }
+            }),
+            uv: uvs.as_ref().and_then(|t| t.get(i)).cloned(),
+        })
+        .collect();
+
+    let indices: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+        None => (0 .. positions.len()).collect(),
+    };
+    let triangles: Vec<[usize; 3]> = match primitive.mode() {
+        Mode::Triangles => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+        // Every other triangle of a strip is reversed to keep the winding.
+        Mode::TriangleStrip => indices.windows(3).enumerate()
+            .map(|(i, t)| if i % 2 == 0 { [t[0], t[1], t[2]] } else { [t[1], t[0], t[2]] })
+            .collect(),
+        Mode::TriangleFan => indices.windows(2).skip(1)
+            .map(|t| [indices[0], t[0], t[1]])
+            .collect(),
+        mode => return Err(ImportError::Unsupported {
+            location: Location::Unknown,
+            what: format!("primitive mode [{:?}]", mode),
+        }),
+    };
+    if let Some(i) = triangles.iter().flatten().find(|i| **i >= positions.len()) {
+        return Err(ImportError::IndexOutOfRange { location: Location::Unknown, index: *i as i64, count: positions.len() });
+    }
+
+    Ok((vertices, normals, triangles))
+}
+
+// Add the meshes of a node and of its children to the mesh, transformed to
+// the scene space. Each node with a mesh becomes a group of faces.
+fn load_node(node: &Node, parent: &Matrix4, buffers: &[Vec<u8>], mesh: &mut Mesh,
+             normals: &mut Vec<Option<[Vector3; 3]>>) -> Result<(), ImportError> {
+    let m = *parent * node_matrix(node);
+
+    if let Some(gltf_mesh) = node.mesh() {
+        let normal_mat = m.normal_matrix();
+        // Mirroring transforms flip the winding of the triangles.
+        let flip = m.determinant() < 0.;
+        let start = mesh.faces.len();
+
+        for primitive in gltf_mesh.primitives() {
+            // Points and lines are left out.
+            if let mode @ (Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip) = primitive.mode() {
+                mesh.warnings.push(ImportWarning {
+                    file: None,
+                    error: ImportError::Unsupported {
+                        location: Location::Unknown,
+                        what: format!("primitive mode [{:?}] in mesh [{}]", mode, gltf_mesh.index()),
+                    },
+                });
+                continue;
+            }
+            let (vertices, vertex_normals, triangles) = read_primitive(&primitive, buffers)?;
+            let offset = mesh.vertices.len();
+            for v in vertices {
+                mesh.vertices.push(Vertex { pt: (&m * v.pt).0, ..v });
+            }
+
+            let material = primitive.material().index();
+            for t in triangles {
+                let t = if flip { [t[0], t[2], t[1]] } else { t };
+                mesh.faces.push(Face::with_material(offset + t[0], offset + t[1], offset + t[2], material));
+                normals.push(vertex_normals.as_ref().map(|n| {
+                    let n = |i: usize| normal_mat.transform_dir(n[i]).normalize();
+                    [n(t[0]), n(t[1]), n(t[2])]
+                }));
+            }
+        }
+
+        mesh.groups.push(Group {
+            object: node.name().unwrap_or("").to_string(),
+            name: gltf_mesh.name().unwrap_or("").to_string(),
+            faces: start .. mesh.faces.len(),
+        });
+    }
+
+    for child in node.children() {
+        load_node(&child, &m, buffers, mesh, normals)?;
+    }
+    Ok(())
+}
+
+// Import a glTF 2.0 file, either JSON (.gltf) with external or embedded
+// buffers, or binary (.glb). External files are looked up in `dir`. The
+// nodes of the default scene are flattened into the mesh with their
+// transforms, and the metallic/roughness materials are loaded along with
+// their base color textures. Images which can not be loaded and primitives
+// of points or lines are left out with a warning. The normals of the file
+// are used if every primitive has them.
+pub fn import<R: Read>(r: &mut R, dir: &Path) -> Result<Mesh, ImportError> {
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+    let gltf = Gltf::from_slice(&data)?;
+    let mut mesh = Mesh::new();
+
+    let buffers = load_buffers(&gltf, dir)?;
+    load_materials(&gltf, &buffers, dir, &mut mesh);
+
+    let scene = gltf.default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or_else(|| ImportError::parse(Location::Unknown, "", "scene"))?;
+
+    let mut normals = vec!();
+    for node in scene.nodes() {
+        load_node(&node, &Matrix4::identity(), &buffers, &mut mesh, &mut normals)?;
+    }
+
+    if !normals.is_empty() && normals.iter().all(|n| n.is_some()) {
+        mesh.normals = normals.into_iter().flatten().collect();
+    }
+
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use crate::framebuffer::Framebuffer;
+    use crate::io::image::write_png;
+
+    use super::*;
+
+    #[test]
+    fn uri_paths() {
+        assert_eq!(uri_path("textures/a%20b%23c%C3%A9.png").unwrap(), "textures/a b#cé.png");
+        assert_eq!(uri_path("100%25").unwrap(), "100%");
+        assert_eq!(uri_path("plain.bin").unwrap(), "plain.bin");
+        for bad in &["a%2", "a%zz", "%", "%FF"] {
+            assert!(matches!(uri_path(bad), Err(ImportError::Parse { .. })), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn bad_images() {
+        let dir = std::env::temp_dir().join(format!("gltf_bad_images_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_png(&mut File::create(dir.join("tex #1é.png")).unwrap(), &Framebuffer::new(2, 2)).unwrap();
+        fs::write(dir.join("photo.jpg"), b"\xff\xd8\xff\xe0 not really a photo").unwrap();
+
+        let positions: Vec<u8> = [0f32, 0., 0., 1., 0., 0., 0., 1., 0.].iter()
+            .flat_map(|c| c.to_le_bytes().to_vec())
+            .collect();
+        let json = format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "buffers": [{{"byteLength": 36, "uri": "data:application/octet-stream;base64,{}"}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+            "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                            "min": [0, 0, 0], "max": [1, 1, 0]}}],
+            "images": [{{"uri": "tex%20%231%C3%A9.png"}}, {{"uri": "photo.jpg"}}, {{"uri": "missing.png"}}],
+            "textures": [{{"source": 0}}, {{"source": 1}}, {{"source": 2}}, {{"source": 0}}],
+            "materials": [
+                {{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0}}}}}},
+                {{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 1}}}}}},
+                {{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 2}}}}}},
+                {{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 3}}}}}}
+            ],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "material": 1}}]}}],
+            "nodes": [{{"mesh": 0}}],
+            "scenes": [{{"nodes": [0]}}]
+        }}"#, BASE64.encode(&positions));
+
+        let mesh = import(&mut json.as_bytes(), &dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let mesh = mesh.unwrap();
+
+        assert_eq!(mesh.faces.len(), 1);
+        assert_eq!(mesh.faces[0].material, Some(1));
+        let textures: Vec<_> = mesh.materials.iter().map(|m| m.texture).collect();
+        assert_eq!(textures, vec!(Some(0), None, None, Some(1)));
+        assert_eq!(mesh.textures.len(), 2);
+
+        let warnings: Vec<_> = mesh.warnings.iter()
+            .map(|w| (w.file.as_ref().and_then(|f| f.file_name()).map(|f| f.to_string_lossy().into_owned()), &w.error))
+            .collect();
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(warnings[0], (Some(ref f), ImportError::Unsupported { .. }) if f == "photo.jpg"));
+        assert!(matches!(warnings[1], (Some(ref f), ImportError::Io(_)) if f == "missing.png"));
+    }
+}
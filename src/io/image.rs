@@ -0,0 +1,251 @@
+
+use std::io::prelude::*;
+use std::io::{self, BufReader, BufWriter};
+use std::fs::File;
+use std::path::Path;
+
+use crate::framebuffer::Framebuffer;
+use crate::color::Color;
+
+// Decoded image, row by row from the top left corner.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Binary PPM (P6). The alpha channel is dropped.
+pub fn write_ppm<W: Write>(w: &mut W, fb: &Framebuffer) -> io::Result<()> {
+    write!(w, "P6\n{} {}\n255\n", fb.width, fb.height)?;
+    let data: Vec<u8> = fb.color.iter()
+        .flat_map(|c| vec![c.r, c.g, c.b])
+        .collect();
+    w.write_all(&data)
+}
+
+// 8-bit RGBA PNG.
+pub fn write_png<W: Write>(w: &mut W, fb: &Framebuffer) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(w, fb.width as u32, fb.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = fb.color.iter()
+        .flat_map(|c| vec![c.r, c.g, c.b, c.a])
+        .collect();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+// Write the framebuffer to a file, the format is chosen from the extension.
+pub fn save<P: AsRef<Path>>(path: P, fb: &Framebuffer) -> io::Result<()> {
+    let path = path.as_ref();
+    let ext = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("png") => write_png(&mut BufWriter::new(File::create(path)?), fb),
+        Some("ppm") => write_ppm(&mut BufWriter::new(File::create(path)?), fb),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("unsupported image format [{}]", path.display()))),
+    }
+}
+
+// PNG of any color type and bit depth, reduced to 8-bit RGBA.
+pub fn read_png<R: Read>(r: R) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(r);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        t => return Err(invalid_data(format!("unsupported PNG color type [{:?}]", t))),
+    };
+    let pixels = data[.. info.buffer_size()]
+        .chunks(channels)
+        .map(|p| match *p {
+            [l] => Color::rgb(l, l, l),
+            [l, a] => Color::rgba(l, l, l, a),
+            [r, g, b] => Color::rgb(r, g, b),
+            [r, g, b, a] => Color::rgba(r, g, b, a),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    Ok(Image { width: info.width as usize, height: info.height as usize, pixels })
+}
+
+// Binary (P6) or plain (P3) PPM, with any maximum value.
+pub fn read_ppm<R: Read>(mut r: R) -> io::Result<Image> {
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+
+    // The header is made of four whitespace separated fields, with comments
+    // running from '#' to the end of the line.
+    let mut pos = 0;
+    let mut next_field = || {
+        loop {
+            match data.get(pos) {
+                Some(b'#') => while pos < data.len() && data[pos] != b'\n' { pos += 1 },
+                Some(c) if c.is_ascii_whitespace() => pos += 1,
+                _ => break,
+            }
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        String::from_utf8_lossy(&data[start .. pos]).into_owned()
+    };
+
+    let magic = next_field();
+    let mut header = [0usize; 3];
+    for h in header.iter_mut() {
+        let field = next_field();
+        *h = field.parse().map_err(|_| invalid_data(format!("invalid PPM header field [{}]", field)))?;
+    }
+    let [width, height, max] = header;
+    if max == 0 || max > 65535 {
+        return Err(invalid_data(format!("invalid PPM maximum value [{}]", max)));
+    }
+    let scale = |v: usize| (v.min(max) * 255 / max) as u8;
+
+    let samples: Vec<u8> = match magic.as_str() {
+        "P6" => {
+            // A single whitespace separates the header from the samples.
+            let body = data.get(pos + 1 ..).unwrap_or(&[]);
+            if max < 256 {
+                body.iter().map(|v| scale(*v as usize)).collect()
+            } else {
+                body.chunks_exact(2).map(|v| scale(u16::from_be_bytes([v[0], v[1]]) as usize)).collect()
+            }
+        },
+        "P3" => String::from_utf8_lossy(&data[pos ..])
+            .split_ascii_whitespace()
+            .map(|v| v.parse().map(scale))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid_data("invalid PPM sample".to_string()))?,
+        _ => return Err(invalid_data(format!("unsupported PPM format [{}]", magic))),
+    };
+    let size = width.checked_mul(height).and_then(|n| n.checked_mul(3))
+        .ok_or_else(|| invalid_data(format!("PPM image too large [{}x{}]", width, height)))?;
+    if samples.len() < size {
+        return Err(invalid_data("truncated PPM data".to_string()));
+    }
+
+    let pixels = samples.chunks(3)
+        .take(width * height)
+        .map(|p| Color::rgb(p[0], p[1], p[2]))
+        .collect();
+    Ok(Image { width, height, pixels })
+}
+
+// Uncompressed or run-length encoded TGA, in true color or grayscale.
+pub fn read_tga<R: Read>(mut r: R) -> io::Result<Image> {
+    let mut header = [0u8; 18];
+    r.read_exact(&mut header)?;
+    let id_len = header[0] as usize;
+    let image_type = header[2];
+    let width = u16::from_le_bytes([header[12], header[13]]) as usize;
+    let height = u16::from_le_bytes([header[14], header[15]]) as usize;
+    let bytes = header[16] as usize / 8;
+    let descriptor = header[17];
+
+    let (rle, gray) = match image_type {
+        2 => (false, false),
+        3 => (false, true),
+        10 => (true, false),
+        11 => (true, true),
+        _ => return Err(invalid_data(format!("unsupported TGA image type [{}]", image_type))),
+    };
+    let valid_depth = if gray { bytes == 1 || bytes == 2 } else { bytes == 3 || bytes == 4 };
+    if header[1] != 0 || !valid_depth {
+        return Err(invalid_data("unsupported TGA pixel format".to_string()));
+    }
+
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+    let data = data.get(id_len ..).unwrap_or(&[]);
+    let truncated = || invalid_data("truncated TGA data".to_string());
+
+    // Check the size against the data before allocating the pixels, a run
+    // length packet holding at most 128 pixels.
+    let max_pixels = if rle { data.len() / (1 + bytes) * 128 } else { data.len() / bytes };
+    if width * height > max_pixels {
+        return Err(truncated());
+    }
+    let mut data = data.iter().cloned();
+
+    // Pixels are stored as BGR(A) or gray level and alpha.
+    let read_pixel = |data: &mut dyn Iterator<Item = u8>| -> io::Result<Color> {
+        let mut p = [0u8; 4];
+        for v in p.iter_mut().take(bytes) {
+            *v = data.next().ok_or_else(truncated)?;
+        }
+        Ok(match (gray, bytes) {
+            (true, 1) => Color::rgb(p[0], p[0], p[0]),
+            (true, _) => Color::rgba(p[0], p[0], p[0], p[1]),
+            (false, 3) => Color::rgb(p[2], p[1], p[0]),
+            (false, _) => Color::rgba(p[2], p[1], p[0], p[3]),
+        })
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    while pixels.len() < width * height {
+        if rle {
+            // The high bit of the packet header tells a run of a single
+            // pixel from a packet of raw pixels, the low bits give the count.
+            let packet = data.next().ok_or_else(truncated)?;
+            let count = (packet & 0x7f) as usize + 1;
+            if packet & 0x80 != 0 {
+                let pixel = read_pixel(&mut data)?;
+                pixels.extend(std::iter::repeat_n(pixel, count));
+            } else {
+                for _ in 0 .. count {
+                    pixels.push(read_pixel(&mut data)?);
+                }
+            }
+        } else {
+            pixels.push(read_pixel(&mut data)?);
+        }
+    }
+    pixels.truncate(width * height);
+
+    // Rows are stored bottom to top unless bit 5 of the descriptor is set,
+    // and left to right unless bit 4 is set.
+    if descriptor & 0x10 != 0 {
+        for row in pixels.chunks_mut(width.max(1)) {
+            row.reverse();
+        }
+    }
+    if descriptor & 0x20 == 0 {
+        pixels = pixels.chunks(width.max(1)).rev().flatten().cloned().collect();
+    }
+    Ok(Image { width, height, pixels })
+}
+
+// Read an image file, the format is chosen from the extension.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Image> {
+    let path = path.as_ref();
+    let ext = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("png") => read_png(BufReader::new(File::open(path)?)),
+        Some("ppm") => read_ppm(BufReader::new(File::open(path)?)),
+        Some("tga") => read_tga(BufReader::new(File::open(path)?)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("unsupported image format [{}]", path.display()))),
+    }
+}
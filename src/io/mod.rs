@@ -1,2 +1,9 @@
 
 pub mod off;
+pub mod obj;
+pub mod stl;
+pub mod ply;
+pub mod gltf;
+pub mod image;
+pub mod error;
+pub mod format;
@@ -0,0 +1,448 @@
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::fs::File;
+use std::path::Path;
+use std::collections::HashMap;
+
+use crate::color::Color;
+use crate::vector::Vector3;
+use crate::material::Material;
+use crate::texture::Texture;
+use crate::mesh::*;
+use crate::io::error::{ImportError, ImportWarning, Location, parse_token};
+
+// Logical lines of a file with the number of their first line: comments are
+// stripped, blank lines are skipped and lines ending with a backslash are
+// joined with the next one. The joined lines keep their line break in place
+// of the backslash, so that tokens are located on their own line.
+fn logical_lines<R: BufRead>(reader: R) -> impl Iterator<Item = io::Result<(usize, String)>> {
+    let mut lines = reader.lines().enumerate();
+    std::iter::from_fn(move || {
+        let mut line = String::new();
+        let mut start = None;
+        loop {
+            let (i, l) = lines.next()?;
+            let l = match l {
+                Ok(l) => l,
+                Err(e) => return Some(Err(e)),
+            };
+            let l = l.split('#').next().unwrap_or("").trim_end();
+            let first = *start.get_or_insert(i + 1);
+            match l.strip_suffix('\\') {
+                Some(l) => {
+                    line.push_str(l);
+                    line.push('\n');
+                },
+                None => {
+                    line.push_str(l);
+                    if !line.trim().is_empty() {
+                        return Some(Ok((first, line)));
+                    }
+                    line.clear();
+                    start = None;
+                },
+            }
+        }
+    })
+}
+
+fn parse_floats(line: usize, text: &str, tokens: &[&str]) -> Result<Vec<f32>, ImportError> {
+    tokens.iter()
+        .map(|t| parse_token(line, text, t, "number"))
+        .collect()
+}
+
+// Indices are 1-based, negative ones count back from the last element.
+fn resolve_index(line: usize, text: &str, token: &str, len: usize) -> Result<usize, ImportError> {
+    let index: i64 = parse_token(line, text, token, "index")?;
+    let i = if index < 0 { len as i64 + index } else { index - 1 };
+    if i >= 0 && (i as usize) < len {
+        Ok(i as usize)
+    } else {
+        Err(ImportError::IndexOutOfRange { location: Location::token(line, text, token), index, count: len })
+    }
+}
+
+// At least `n` values after the keyword.
+fn expect_values(line: usize, tokens: &[&str], what: &'static str, n: usize) -> Result<(), ImportError> {
+    if tokens.len() < n + 1 {
+        return Err(ImportError::CountMismatch {
+            location: Location::Text { line, column: 1 },
+            what,
+            expected: n,
+            found: tokens.len() - 1,
+        });
+    }
+    Ok(())
+}
+
+fn parse_mtl_color(line: usize, text: &str, tokens: &[&str]) -> Result<Color, ImportError> {
+    let location = Location::token(line, text, tokens.first().unwrap_or(&""));
+    if let Some(&kind @ ("spectral" | "xyz")) = tokens.first() {
+        return Err(ImportError::Unsupported { location, what: format!("{} color", kind) });
+    }
+    // A single value is a gray level.
+    let c: Vec<f32> = tokens.iter()
+        .map(|t| parse_token(line, text, t, "material color"))
+        .collect::<Result<_, _>>()?;
+    match c.len() {
+        1 => Ok(Color::from_f32(c[0], c[0], c[0], 1.)),
+        3 => Ok(Color::from_f32(c[0], c[1], c[2], 1.)),
+        _ => Err(ImportError::parse(location, &tokens.join(" "), "material color")),
+    }
+}
+
+// Read the materials of an MTL library into the mesh, returning their
+// indices by name. Texture paths are relative to the library. The
+// statements which can not be read are left out and reported as warnings,
+// along with the textures which can not be loaded.
+fn import_mtl(path: &Path, mesh: &mut Mesh) -> Result<HashMap<String, usize>, ImportError> {
+    let f = File::open(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut names = HashMap::new();
+    let mut textures: HashMap<String, usize> = HashMap::new();
+    let mut current = None;
+
+    let warn = |mesh: &mut Mesh, error| {
+        mesh.warnings.push(ImportWarning { file: Some(path.to_path_buf()), error });
+    };
+
+    for l in logical_lines(BufReader::new(f)) {
+        let (line, l) = l?;
+        let tokens: Vec<&str> = l.split_whitespace().collect();
+
+        if tokens[0] == "newmtl" {
+            let name = tokens[1 ..].join(" ");
+            mesh.materials.push(Material::default());
+            names.insert(name, mesh.materials.len() - 1);
+            current = Some(mesh.materials.len() - 1);
+            continue;
+        }
+
+        let current = match current {
+            Some(i) => i,
+            None => {
+                warn(mesh, ImportError::Unsupported {
+                    location: Location::Text { line, column: 1 },
+                    what: format!("statement [{}] before newmtl", tokens[0]),
+                });
+                continue;
+            }
+        };
+        let args = &tokens[1 ..];
+
+        match tokens[0] {
+            "Kd" | "Ks" | "Ka" | "Ke" => {
+                let color = match parse_mtl_color(line, &l, args) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        warn(mesh, e);
+                        continue;
+                    }
+                };
+                let material = &mut mesh.materials[current];
+                match tokens[0] {
+                    "Kd" => material.diffuse = Color { a: material.diffuse.a, ..color },
+                    "Ks" => material.specular = color,
+                    "Ka" => material.ambient = color,
+                    _ => material.emissive = color,
+                }
+            },
+            "Ns" | "d" | "Tr" | "Pr" | "Pm" => {
+                let v = match parse_token::<f32>(line, &l, args.first().unwrap_or(&""), "material value") {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn(mesh, e);
+                        continue;
+                    }
+                };
+                let material = &mut mesh.materials[current];
+                match tokens[0] {
+                    "Ns" => material.shininess = v,
+                    // Opacity, or transparency for Tr, stored as the alpha
+                    // of the diffuse color.
+                    "d" => material.diffuse.a = (v.clamp(0., 1.) * 255.).round() as u8,
+                    "Tr" => material.diffuse.a = ((1. - v.clamp(0., 1.)) * 255.).round() as u8,
+                    "Pr" => material.roughness = v,
+                    _ => material.metallic = v,
+                }
+            },
+            "map_Kd" => {
+                // Options come before the file name, which is the last token.
+                let file = match args.last() {
+                    Some(file) => file.replace('\\', "/"),
+                    None => continue,
+                };
+                if let Some(i) = textures.get(&file) {
+                    mesh.materials[current].texture = Some(*i);
+                    continue;
+                }
+                let texture_path = dir.join(&file);
+                match Texture::load(&texture_path) {
+                    Ok(texture) => {
+                        mesh.textures.push(texture);
+                        let i = mesh.textures.len() - 1;
+                        mesh.materials[current].texture = Some(i);
+                        textures.insert(file, i);
+                    },
+                    Err(e) => mesh.warnings.push(ImportWarning { file: Some(texture_path), error: e.into() }),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok(names)
+}
+
+// Import a Wavefront OBJ file. Material libraries are looked up in `dir`,
+// usually the directory of the file. Polygons are split into triangle fans,
+// groups and objects are kept as named ranges of faces. The normals of the
+// file are used if every face has them.
+pub fn import<R: Read>(r: &mut R, dir: &Path) -> Result<Mesh, ImportError> {
+    let reader = BufReader::new(r);
+    let mut mesh = Mesh::new();
+
+    let mut positions: Vec<(Vector3, Option<Color>)> = vec!();
+    let mut uvs: Vec<[f32; 2]> = vec!();
+    let mut normals: Vec<Vector3> = vec!();
+    // Mesh vertex of each distinct pair of position and texture coordinates.
+    let mut vertex_indices: HashMap<(usize, Option<usize>), usize> = HashMap::new();
+    let mut face_normals: Vec<Option<[Vector3; 3]>> = vec!();
+
+    let mut materials: HashMap<String, usize> = HashMap::new();
+    let mut material: Option<usize> = None;
+    let mut object = String::new();
+    let mut group = String::new();
+    let mut group_start = 0;
+
+    for l in logical_lines(reader) {
+        let (line, l) = l?;
+        let tokens: Vec<&str> = l.split_whitespace().collect();
+        let args = &tokens[1 ..];
+
+        match tokens[0] {
+            "v" => {
+                // Positions can be followed by a w component, which is
+                // ignored, or by an RGB vertex color.
+                expect_values(line, &tokens, "vertex coordinates", 3)?;
+                let c = parse_floats(line, &l, args)?;
+                let color = if c.len() >= 6 {
+                    Some(Color::from_f32(c[3], c[4], c[5], 1.))
+                } else {
+                    None
+                };
+                positions.push((Vector3::new(c[0], c[1], c[2]), color));
+            },
+            "vt" => {
+                expect_values(line, &tokens, "texture coordinates", 1)?;
+                let c = parse_floats(line, &l, args)?;
+                // OBJ puts v = 0 at the bottom of the image.
+                uvs.push([c[0], 1. - c.get(1).cloned().unwrap_or(0.)]);
+            },
+            "vn" => {
+                expect_values(line, &tokens, "normal coordinates", 3)?;
+                let c = parse_floats(line, &l, args)?;
+                normals.push(Vector3::new(c[0], c[1], c[2]));
+            },
+            "f" => {
+                expect_values(line, &tokens, "face vertices", 3)?;
+
+                // Each corner is v, v/vt, v//vn or v/vt/vn.
+                let mut corners = vec!();
+                for corner in args {
+                    let mut refs = corner.split('/');
+                    let v = resolve_index(line, &l, refs.next().unwrap_or(""), positions.len())?;
+                    let vt = match refs.next() {
+                        Some("") | None => None,
+                        Some(vt) => Some(resolve_index(line, &l, vt, uvs.len())?),
+                    };
+                    let vn = match refs.next() {
+                        Some("") | None => None,
+                        Some(vn) => Some(resolve_index(line, &l, vn, normals.len())?),
+                    };
+                    corners.push((v, vt, vn));
+                }
+
+                let indices: Vec<usize> = corners.iter()
+                    .map(|&(v, vt, _)| *vertex_indices.entry((v, vt)).or_insert_with(|| {
+                        let (pt, color) = positions[v];
+                        mesh.vertices.push(Vertex { pt, color, uv: vt.map(|i| uvs[i]) });
+                        mesh.vertices.len() - 1
+                    }))
+                    .collect();
+
+                for f in 0 .. corners.len() - 2 {
+                    let fan = [0, f + 1, f + 2];
+                    mesh.faces.push(Face::with_material(indices[fan[0]], indices[fan[1]], indices[fan[2]], material));
+
+                    let n: Option<Vec<Vector3>> = fan.iter()
+                        .map(|&k| corners[k].2.map(|i| normals[i].normalize()))
+                        .collect();
+                    face_normals.push(n.map(|n| [n[0], n[1], n[2]]));
+                }
+            },
+            "o" | "g" => {
+                let name = args.join(" ");
+                close_group(&mut mesh, &object, &group, group_start);
+                group_start = mesh.faces.len();
+                if tokens[0] == "o" {
+                    object = name;
+                    group.clear();
+                } else {
+                    group = name;
+                }
+            },
+            "mtllib" => {
+                // The mesh is still loaded without the materials of a
+                // library which can not be read.
+                for file in args {
+                    let path = dir.join(file);
+                    match import_mtl(&path, &mut mesh) {
+                        Ok(names) => materials.extend(names),
+                        Err(error) => mesh.warnings.push(ImportWarning { file: Some(path), error }),
+                    }
+                }
+            },
+            "usemtl" => {
+                // The faces of an unknown material get the mesh material.
+                let name = args.join(" ");
+                material = materials.get(&name).cloned();
+                if material.is_none() {
+                    mesh.warnings.push(ImportWarning {
+                        file: None,
+                        error: ImportError::UnknownName {
+                            location: Location::token(line, &l, args.first().unwrap_or(&"")),
+                            what: "material",
+                            name,
+                        },
+                    });
+                }
+            },
+            // Smoothing groups, line elements and the rest are ignored.
+            _ => {},
+        }
+    }
+    close_group(&mut mesh, &object, &group, group_start);
+
+    if !face_normals.is_empty() && face_normals.iter().all(|n| n.is_some()) {
+        mesh.normals = face_normals.into_iter().flatten().collect();
+    }
+
+    Ok(mesh)
+}
+
+// Record the faces read since the last o or g statement.
+fn close_group(mesh: &mut Mesh, object: &str, group: &str, start: usize) {
+    if mesh.faces.len() > start {
+        mesh.groups.push(Group {
+            object: object.to_string(),
+            name: group.to_string(),
+            faces: start .. mesh.faces.len(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn import_str(data: &str) -> Result<Mesh, ImportError> {
+        import(&mut data.as_bytes(), Path::new(""))
+    }
+
+    fn faces(mesh: &Mesh) -> Vec<[usize; 3]> {
+        mesh.faces.iter().map(|f| [f.a, f.b, f.c]).collect()
+    }
+
+    #[test]
+    fn negative_indices() {
+        let mesh = import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 1 1 0\nf 2 -1 -2\n").unwrap();
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [1, 3, 2]));
+
+        assert!(matches!(import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -4\n"),
+                         Err(ImportError::IndexOutOfRange { location: Location::Text { line: 4, column: 9 }, index: -4, count: 3 })));
+        assert!(matches!(import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n"),
+                         Err(ImportError::IndexOutOfRange { index: 0, .. })));
+    }
+
+    #[test]
+    fn polygon_fans() {
+        let mesh = import_str("v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n").unwrap();
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [0, 2, 3], [0, 3, 4]));
+
+        assert!(matches!(import_str("v 0 0 0\nv 1 0 0\nf 1 2\n"),
+                         Err(ImportError::CountMismatch { location: Location::Text { line: 3, column: 1 }, expected: 3, found: 2, .. })));
+    }
+
+    #[test]
+    fn corners() {
+        let data = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0.25\nvn 0 0 2\nvn 0 2 0\n\
+            f 1 2 3\nf 1/1 2/2 3/1\nf 1//1 2//1 3//1\nf 1/1/2 2/2/2 3/1/2\n";
+        let mesh = import_str(data).unwrap();
+        // A vertex for each pair of position and texture coordinates.
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [3, 4, 5], [0, 1, 2], [3, 4, 5]));
+        let uvs: Vec<_> = mesh.vertices.iter().map(|v| v.uv).collect();
+        assert_eq!(uvs, vec!(None, None, None, Some([0., 1.]), Some([1., 0.75]), Some([0., 1.])));
+        // Normals are only used when every face has them.
+        assert!(mesh.normals.is_empty());
+
+        let mesh = import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 2\nvn 0 2 0\nf 1//1 2/1/2 3//1\n").unwrap();
+        let n: Vec<_> = mesh.normals[0].iter().map(|n| [n.x, n.y, n.z]).collect();
+        assert_eq!(n, vec!([0., 0., 1.], [0., 1., 0.], [0., 0., 1.]));
+
+        assert!(matches!(import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nf 1/1 2/2 3/1\n"),
+                         Err(ImportError::IndexOutOfRange { location: Location::Text { line: 5, column: 9 }, index: 2, count: 1 })));
+        assert!(matches!(import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1//x 2 3\n"),
+                         Err(ImportError::Parse { location: Location::Text { line: 4, column: 6 }, .. })));
+    }
+
+    #[test]
+    fn groups() {
+        let data = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\no car\ng body paint\nf 1 2 3\nf 2 4 3\n\
+            g wheels\ng\nf 1 2 4\no\nf 1 3 4\n";
+        let mesh = import_str(data).unwrap();
+        let groups: Vec<_> = mesh.groups.iter().map(|g| (g.object.as_str(), g.name.as_str(), g.faces.clone())).collect();
+        assert_eq!(groups, vec!(("", "", 0 .. 1), ("car", "body paint", 1 .. 3), ("car", "", 3 .. 4), ("", "", 4 .. 5)));
+    }
+
+    #[test]
+    fn continued_lines() {
+        let mesh = import_str("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 \\\n  3 4 # quad\n").unwrap();
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [0, 2, 3]));
+
+        assert!(matches!(import_str("v 0 0 \\\n0\nv 1 0 0\nv 0 1 0\nf 1 2 \\\n  3 \\\n  9\n"),
+                         Err(ImportError::IndexOutOfRange { location: Location::Text { line: 7, column: 3 }, .. })));
+        assert!(matches!(import_str("v 0 \\\n   0 zero\n"),
+                         Err(ImportError::Parse { location: Location::Text { line: 2, column: 6 }, .. })));
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = std::env::temp_dir().join(format!("obj_missing_files_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("paint.mtl"), "Kd 1 0 0\nnewmtl red\nKd 1 0 0\nmap_Kd missing.png\nKs 1 0\n").unwrap();
+
+        let data = "mtllib nowhere.mtl paint.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\n";
+        let mesh = import(&mut data.as_bytes(), &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let faces: Vec<_> = mesh.faces.iter().map(|f| f.material).collect();
+        assert_eq!(faces, vec!(Some(0), None));
+        assert_eq!(mesh.materials[0].diffuse, Color::rgba(255, 0, 0, 255));
+        assert_eq!(mesh.materials[0].texture, None);
+
+        let warnings: Vec<_> = mesh.warnings.iter()
+            .map(|w| (w.file.as_ref().and_then(|f| f.file_name()).map(|f| f.to_string_lossy().into_owned()), &w.error))
+            .collect();
+        assert_eq!(warnings.len(), 5);
+        assert!(matches!(warnings[0], (Some(ref f), ImportError::Io(_)) if f == "nowhere.mtl"));
+        assert!(matches!(warnings[1], (Some(ref f), ImportError::Unsupported { location: Location::Text { line: 1, column: 1 }, .. }) if f == "paint.mtl"));
+        assert!(matches!(warnings[2], (Some(ref f), _) if f == "missing.png"));
+        assert!(matches!(warnings[3], (Some(ref f), ImportError::Parse { location: Location::Text { line: 5, column: 4 }, .. }) if f == "paint.mtl"));
+        assert!(matches!(warnings[4], (None, ImportError::UnknownName { location: Location::Text { line: 7, column: 8 }, .. })));
+    }
+}
@@ -1,107 +1,505 @@
-
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::fs::File;
+use std::io;
+use std::collections::HashSet;
+use std::iter::Peekable;
 
-use sfml::graphics::Color;
+use crate::color::Color;
 use crate::vector::Vector3;
 use crate::mesh::*;
+use crate::io::error::{ImportError, Location, parse_token};
+
+// Colors are given either as integers in [0, 255] or as floats in [0, 1],
+// with an optional alpha component. A single value is an index in a color
+// map, which is not supported.
+fn parse_color(tokens: &[&str]) -> Option<Color> {
+    if tokens.len() != 3 && tokens.len() != 4 {
+        return None;
+    }
 
-macro_rules! face {
-    ($a:expr, $b:expr, $c:expr) => (Face { a: $a, b: $b, c: $c, color: None })
+    if tokens.iter().all(|t| t.parse::<u8>().is_ok()) {
+        let c: Vec<u8> = tokens.iter().map(|t| t.parse().unwrap()).collect();
+        let a = if c.len() == 4 { c[3] } else { 255 };
+        return Some(Color::rgba(c[0], c[1], c[2], a));
+    }
+
+    let c: Vec<f32> = tokens.iter()
+        .map(|t| t.parse::<f32>().ok())
+        .collect::<Option<_>>()?;
+    let a = if c.len() == 4 { c[3] } else { 1. };
+    Some(Color::from_f32(c[0], c[1], c[2], a))
 }
 
-pub fn import(f: &mut File, mesh: &mut Mesh) -> Option<()> {
-    let reader = BufReader::new(f);
-    let mut lines = reader.lines()
-        .map(|s| s.unwrap())
-        .filter(|s| s.len() > 0)
-        .filter(|s| s.as_bytes()[0] != b'#');
+// Optional data announced by the prefixes of the header keyword, which is
+// [ST][C][N][4][n]OFF, possibly followed by BINARY.
+struct Header {
+    // Texture coordinates after the other vertex data.
+    texture: bool,
+    // Vertex colors after the normals.
+    color: bool,
+    normal: bool,
+    // An extra homogeneous coordinate.
+    four: bool,
+    // The dimension is given before the counts.
+    ndim: bool,
+    binary: bool,
+}
 
-    let fmt = lines.next()?;
-    if fmt != "OFF" {
-        println!("unrecognized format [{}]", fmt);
-        return None;
+fn parse_header(tokens: &[&str]) -> Option<Header> {
+    let mut k = tokens.first()?.strip_suffix("OFF")?;
+    let mut prefix = |p: &str| match k.strip_prefix(p) {
+        Some(rest) => {
+            k = rest;
+            true
+        },
+        None => false,
+    };
+    let header = Header {
+        texture: prefix("ST"),
+        color: prefix("C"),
+        normal: prefix("N"),
+        four: prefix("4"),
+        ndim: prefix("n"),
+        binary: tokens.get(1) == Some(&"BINARY"),
+    };
+    if k.is_empty() { Some(header) } else { None }
+}
+
+impl Header {
+    // Coordinates of each vertex, only the first three are used.
+    fn coords(&self, ndim: usize) -> usize {
+        (if self.ndim { ndim } else { 3 }) + self.four as usize
     }
+}
 
-    let sizes: Vec<usize> = lines.next()?
-        .split(' ')
-        .filter_map(|s| s.parse::<usize>().ok())
-        .collect();
+fn position(c: &[f32]) -> Vector3 {
+    let c = |i: usize| c.get(i).cloned().unwrap_or(0.);
+    Vector3::new(c(0), c(1), c(2))
+}
 
-    if sizes.len() != 3 {
-        println!("invalid sizes");
-        return None;
+// Split a polygon into a fan of faces, recording it in the mesh.
+fn push_polygon(mesh: &mut Mesh, verts: Vec<usize>, color: Option<Color>) {
+    let start = mesh.faces.len();
+    for f in 0 .. verts.len() - 2 {
+        mesh.faces.push(Face { color, ..Face::new(verts[0], verts[f + 1], verts[f + 2]) });
     }
+    mesh.polygons.push(Polygon { vertices: verts, faces: start .. mesh.faces.len() });
+}
 
-    let (nr_vertices, nr_faces, nr_edges) = (sizes[0], sizes[1], sizes[2]);
+// Split the data of a line from its comment, which is returned without its
+// leading #.
+fn split_comment(line: &str) -> (&str, Option<String>) {
+    match line.split_once('#') {
+        Some((data, comment)) => (data, Some(comment.trim_end_matches('\r').to_string())),
+        None => (line, None),
+    }
+}
 
-    println!("preparing to load [{}] vertices", nr_vertices);
+// Import any file of the OFF family, in ASCII or binary form. The comments
+// are kept in the mesh along with the element they belong to, whether they
+// are on their own line or follow the data. The vertex normals of NOFF files
+// are used as the normals of the faces.
+pub fn import<R: Read>(r: &mut R) -> Result<Mesh, ImportError> {
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+    let mut mesh = Mesh::new();
+
+    // Lines of the file with their offset, up to the header.
+    let mut offset = 0;
+    let mut line = 0;
+    let mut header_line = None;
+    for l in data.split(|b| *b == b'\n') {
+        let text = String::from_utf8_lossy(l);
+        offset += l.len() + 1;
+        line += 1;
+        let (rest, comment) = split_comment(&text);
+        let blank = rest.trim().is_empty();
+        if let Some(text) = comment {
+            mesh.comments.push(Comment { text, anchor: Anchor::Header, inline: !blank });
+        }
+        if !blank {
+            header_line = Some(text.into_owned());
+            break;
+        }
+    }
 
-    for _i in 0 .. nr_vertices {
-        let coords: Vec<f32> = lines.next()?
-            .split(' ')
-            .filter_map(|s| s.parse::<f32>().ok())
+    let header_line = header_line.ok_or(ImportError::BadHeader {
+        location: Location::EndOfFile,
+        token: String::new(),
+    })?;
+    let text = header_line.split('#').next().unwrap_or("");
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let header = parse_header(&tokens).ok_or_else(|| ImportError::BadHeader {
+        location: Location::token(line, &header_line, tokens[0]),
+        token: tokens[0].to_string(),
+    })?;
+
+    let data = data.get(offset ..).unwrap_or(&[]);
+    let normals = if header.binary {
+        import_binary(&header, data, offset, &mut mesh)?
+    } else {
+        let rest: Vec<(usize, &str, &str)> = tokens[1 ..].iter().map(|t| (line, header_line.as_str(), *t)).collect();
+        import_ascii(&header, rest, line, &String::from_utf8_lossy(data), &mut mesh)?
+    };
+
+    if let Some(n) = normals {
+        mesh.normals = mesh.faces.iter()
+            .map(|f| [n[f.a].normalize(), n[f.b].normalize(), n[f.c].normalize()])
             .collect();
+    }
 
-        let vertex =
-            if coords.len() == 3 {
-                Vertex {
-                    pt: Vector3 { x:coords[0], y:coords[1], z:coords[2] },
-                    color: None
-                }
-            } else if coords.len() == 7 {
-                Vertex {
-                    pt: Vector3 { x:coords[0], y:coords[1], z:coords[2] },
-                    color: None /*Some (color!(coords[3], coords[4], coords[5]))*/
-                }
-            } else {
-                return None;
-            };
+    Ok(mesh)
+}
 
-        mesh.vertices.push(vertex);
+// Lines of an ASCII file which hold data, the comments around them being
+// collected with the element read from the line.
+struct Lines<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    // Number of the line before the text.
+    first_line: usize,
+    // Comments on their own line, since the last line with data.
+    pending: Vec<String>,
+    comments: Vec<Comment>,
+}
+
+impl<'a> Lines<'a> {
+    // The number of the next line with data, its text and the data before
+    // any comment.
+    fn next(&mut self, anchor: Anchor) -> Option<(usize, &'a str, &'a str)> {
+        for (i, text) in &mut self.lines {
+            let (data, comment) = split_comment(text);
+            if data.trim().is_empty() {
+                self.pending.extend(comment);
+                continue;
+            }
+            for text in self.pending.drain(..) {
+                self.comments.push(Comment { text, anchor, inline: false });
+            }
+            if let Some(text) = comment {
+                self.comments.push(Comment { text, anchor, inline: true });
+            }
+            return Some((self.first_line + i + 1, text, data));
+        }
+        None
     }
 
-    println!("loaded {} vertices", mesh.vertices.len());
-    println!("preparing to load [{}] faces", nr_faces);
+    // The comments of the file, including those after the data.
+    fn finish(mut self) -> Vec<Comment> {
+        while self.next(Anchor::End).is_some() {}
+        for text in self.pending.drain(..) {
+            self.comments.push(Comment { text, anchor: Anchor::End, inline: false });
+        }
+        self.comments
+    }
+}
 
-    for _i in 0 .. nr_faces {
-        let l = lines.next()?;
-        let verts: Vec<u64> = l.split(' ')
-            .filter_map(|s| s.parse::<u64>().ok())
-            .collect();
+// Returns the vertex normals, if the file has them. The header tokens after
+// the keyword are given with their line, `first_line` is the line before
+// the text.
+fn import_ascii(header: &Header, rest: Vec<(usize, &str, &str)>, first_line: usize, text: &str,
+                mesh: &mut Mesh) -> Result<Option<Vec<Vector3>>, ImportError> {
+    let mut lines = Lines { lines: text.lines().enumerate(), first_line, pending: vec!(), comments: vec!() };
+
+    // The dimension and the counts can be on the header line or on the
+    // next ones.
+    let nr_sizes = if header.ndim { 4 } else { 3 };
+    let mut sizes: Vec<usize> = vec!();
+    let mut tokens = rest;
+    loop {
+        for (line, text, t) in tokens.iter().take(nr_sizes - sizes.len()) {
+            sizes.push(parse_token(*line, text, t, "count")?);
+        }
+        if sizes.len() == nr_sizes {
+            break;
+        }
+        let (line, text, data) = lines.next(Anchor::Counts).ok_or(ImportError::CountMismatch {
+            location: Location::EndOfFile,
+            what: "counts",
+            expected: nr_sizes,
+            found: sizes.len(),
+        })?;
+        tokens = data.split_whitespace().map(|t| (line, text, t)).collect();
+    }
 
-        if verts.len() == 0 {
-            println!("empty face line");
-            return None;
+    let ndim = if header.ndim { sizes.remove(0) } else { 3 };
+    let (nr_vertices, nr_faces) = (sizes[0], sizes[1]);
+    let nr_coords = header.coords(ndim);
+    let nr_normal = if header.normal { 3 } else { 0 };
+    let nr_texture = if header.texture { 2 } else { 0 };
+
+    let mut normals = vec!();
+    for i in 0 .. nr_vertices {
+        let (line, text, data) = lines.next(Anchor::Vertex(i)).ok_or(ImportError::CountMismatch {
+            location: Location::EndOfFile,
+            what: "vertices",
+            expected: nr_vertices,
+            found: i,
+        })?;
+        let tokens: Vec<&str> = data.split_whitespace().collect();
+        if tokens.len() < nr_coords + nr_normal + nr_texture {
+            return Err(ImportError::CountMismatch {
+                location: Location::Text { line, column: 1 },
+                what: "vertex values",
+                expected: nr_coords + nr_normal + nr_texture,
+                found: tokens.len(),
+            });
         }
 
-        let nr_verts = verts[0] as usize;
-        if verts.len() < nr_verts + 1 || nr_verts < 3 {
-            println!("invalid vertex count [{}]", nr_verts);
-            return None;
+        let floats = |tokens: &[&str]| -> Result<Vec<f32>, ImportError> {
+            tokens.iter().map(|t| parse_token(line, text, t, "coordinate")).collect()
+        };
+        let (coords, rest) = tokens.split_at(nr_coords);
+        let (normal, rest) = rest.split_at(nr_normal);
+        let (color, texture) = rest.split_at(rest.len() - nr_texture);
+        let (coords, normal, texture) = (floats(coords)?, floats(normal)?, floats(texture)?);
+
+        // Colors can also be given without the C prefix. A single value is an
+        // index in a color map, which is not supported.
+        let color = match color.len() {
+            0 | 1 => None,
+            _ => Some(parse_color(color).ok_or_else(|| {
+                ImportError::parse(Location::token(line, text, color[0]), &color.join(" "), "color")
+            })?),
+        };
+
+        if header.normal {
+            normals.push(position(&normal));
         }
+        mesh.vertices.push(Vertex {
+            pt: position(&coords),
+            color,
+            // OFF puts t = 0 at the bottom of the image.
+            uv: if header.texture { Some([texture[0], 1. - texture[1]]) } else { None },
+        });
+    }
+
+    for i in 0 .. nr_faces {
+        let (line, text, data) = lines.next(Anchor::Face(i)).ok_or(ImportError::CountMismatch {
+            location: Location::EndOfFile,
+            what: "faces",
+            expected: nr_faces,
+            found: i,
+        })?;
+        let tokens: Vec<&str> = data.split_whitespace().collect();
+
+        let nr_verts: usize = parse_token(line, text, tokens[0], "vertex count")?;
+        if nr_verts < 3 {
+            return Err(ImportError::parse(Location::token(line, text, tokens[0]), tokens[0], "vertex count"));
+        }
+        if tokens.len() < nr_verts + 1 {
+            return Err(ImportError::CountMismatch {
+                location: Location::Text { line, column: 1 },
+                what: "face vertices",
+                expected: nr_verts,
+                found: tokens.len() - 1,
+            });
+        }
+
+        let mut verts = vec!();
+        for t in &tokens[1 .. nr_verts + 1] {
+            let v: usize = parse_token(line, text, t, "vertex index")?;
+            if v >= mesh.vertices.len() {
+                return Err(ImportError::IndexOutOfRange {
+                    location: Location::token(line, text, t),
+                    index: v as i64,
+                    count: mesh.vertices.len(),
+                });
+            }
+            verts.push(v);
+        }
+
+        // The vertex indices can be followed by the face color, read like
+        // the vertex colors.
+        let color = &tokens[nr_verts + 1 ..];
+        let color = match color.len() {
+            0 | 1 => None,
+            _ => Some(parse_color(color).ok_or_else(|| {
+                ImportError::parse(Location::token(line, text, color[0]), &color.join(" "), "color")
+            })?),
+        };
+        push_polygon(mesh, verts, color);
+    }
+
+    mesh.comments.extend(lines.finish());
+    Ok(if header.normal { Some(normals) } else { None })
+}
+
+// Big-endian 32-bit integers and floats.
+struct Binary<'a> {
+    data: &'a [u8],
+    pos: usize,
+    // Offset of the data in the file.
+    start: usize,
+}
+
+impl<'a> Binary<'a> {
+    fn word(&mut self) -> Result<[u8; 4], ImportError> {
+        let w = self.data.get(self.pos .. self.pos + 4).ok_or(ImportError::CountMismatch {
+            location: Location::Byte(self.start + self.pos),
+            what: "bytes",
+            expected: 4,
+            found: self.data.len() - self.pos.min(self.data.len()),
+        })?;
+        self.pos += 4;
+        Ok([w[0], w[1], w[2], w[3]])
+    }
+
+    fn int(&mut self) -> Result<usize, ImportError> {
+        let i = i32::from_be_bytes(self.word()?);
+        if i < 0 {
+            return Err(ImportError::parse(Location::Byte(self.start + self.pos - 4), &i.to_string(), "count"));
+        }
+        Ok(i as usize)
+    }
+
+    fn floats(&mut self, n: usize) -> Result<Vec<f32>, ImportError> {
+        (0 .. n).map(|_| self.word().map(f32::from_be_bytes)).collect()
+    }
+}
+
+// Binary files hold the same data as ASCII ones, colors being floats. Face
+// colors are preceded by their number of components.
+fn import_binary(header: &Header, data: &[u8], start: usize,
+                 mesh: &mut Mesh) -> Result<Option<Vec<Vector3>>, ImportError> {
+    let mut r = Binary { data, pos: 0, start };
+
+    let ndim = if header.ndim { r.int()? } else { 3 };
+    let (nr_vertices, nr_faces, _nr_edges) = (r.int()?, r.int()?, r.int()?);
 
-        for f in 0 .. nr_verts - 2 {
-            let a = verts[1] as usize;
-            let b = verts[f + 2] as usize;
-            let c = verts[f + 3] as usize;
+    let mut normals = vec!();
+    for _i in 0 .. nr_vertices {
+        let pt = position(&r.floats(header.coords(ndim))?);
+        if header.normal {
+            normals.push(position(&r.floats(3)?));
+        }
+        let color = if header.color {
+            let c = r.floats(4)?;
+            Some(Color::from_f32(c[0], c[1], c[2], c[3]))
+        } else {
+            None
+        };
+        let uv = if header.texture {
+            let t = r.floats(2)?;
+            Some([t[0], 1. - t[1]])
+        } else {
+            None
+        };
+        mesh.vertices.push(Vertex { pt, color, uv });
+    }
 
-            if a >= mesh.vertices.len() ||
-               b >= mesh.vertices.len() ||
-               c >= mesh.vertices.len() {
-                println!("invalid vertex indexes [{}, {}, {}]", a, b, c);
-                return None;
+    for _i in 0 .. nr_faces {
+        let nr_verts = r.int()?;
+        if nr_verts < 3 {
+            return Err(ImportError::parse(Location::Byte(start + r.pos - 4), &nr_verts.to_string(), "vertex count"));
+        }
+        let mut verts = vec!();
+        for _ in 0 .. nr_verts {
+            let v = r.int()?;
+            if v >= mesh.vertices.len() {
+                return Err(ImportError::IndexOutOfRange {
+                    location: Location::Byte(start + r.pos - 4),
+                    index: v as i64,
+                    count: mesh.vertices.len(),
+                });
             }
+            verts.push(v);
+        }
+        let nr_color = r.int()?;
+        let c = r.floats(nr_color)?;
+        let color = match c.len() {
+            3 => Some(Color::from_f32(c[0], c[1], c[2], 1.)),
+            4 => Some(Color::from_f32(c[0], c[1], c[2], c[3])),
+            _ => None,
+        };
+        push_polygon(mesh, verts, color);
+    }
+
+    Ok(if header.normal { Some(normals) } else { None })
+}
+
+fn write_color<W: Write>(w: &mut W, c: Color) -> io::Result<()> {
+    write!(w, " {} {} {} {}", c.r, c.g, c.b, c.a)
+}
+
+type Comments<'a> = Peekable<std::vec::IntoIter<&'a Comment>>;
+
+// Write the comments which go on their own lines before an element.
+fn write_comments<W: Write>(w: &mut W, comments: &mut Comments, anchor: Anchor) -> io::Result<()> {
+    while let Some(c) = comments.next_if(|c| c.anchor == anchor && !c.inline) {
+        writeln!(w, "#{}", c.text)?;
+    }
+    Ok(())
+}
 
-            let face = face!(a, b, c);
-            mesh.faces.push(face);
+// End the line of an element, after its inline comment if it has one.
+fn end_line<W: Write>(w: &mut W, comments: &mut Comments, anchor: Anchor) -> io::Result<()> {
+    while let Some(c) = comments.next_if(|c| c.anchor == anchor && c.inline) {
+        write!(w, " #{}", c.text)?;
+    }
+    writeln!(w)
+}
+
+// Write the mesh as OFF, or as COFF if its vertices have colors. The
+// polygons loaded from an OFF file are written instead of their triangles,
+// along with the comments of the file at the same places. Comments of
+// elements the mesh no longer has are written at the end. Coordinates are
+// written with as many digits as needed to be read back exactly.
+pub fn export<W: Write>(w: &mut W, mesh: &Mesh) -> io::Result<()> {
+    let polygons: Vec<(Vec<usize>, &Face)> = if mesh.polygons.is_empty() {
+        mesh.faces.iter()
+            .map(|f| (vec!(f.a, f.b, f.c), f))
+            .collect()
+    } else {
+        mesh.polygons.iter()
+            .map(|p| (p.vertices.clone(), &mesh.faces[p.faces.start]))
+            .collect()
+    };
+
+    let mut edges = HashSet::new();
+    for (vertices, _) in &polygons {
+        for (i, a) in vertices.iter().enumerate() {
+            let b = vertices[(i + 1) % vertices.len()];
+            edges.insert((*a.min(&b), *a.max(&b)));
         }
     }
 
-    println!("loaded {} faces\n", mesh.faces.len());
+    // In the order of the file, the comments of an element keeping theirs.
+    let mut comments: Vec<&Comment> = mesh.comments.iter().collect();
+    comments.sort_by_key(|c| (c.anchor, c.inline));
+    let mut comments = comments.into_iter().peekable();
+
+    let colored = mesh.vertices.iter().any(|v| v.color.is_some());
+    write_comments(w, &mut comments, Anchor::Header)?;
+    write!(w, "{}", if colored { "COFF" } else { "OFF" })?;
+    end_line(w, &mut comments, Anchor::Header)?;
+    write_comments(w, &mut comments, Anchor::Counts)?;
+    write!(w, "{} {} {}", mesh.vertices.len(), polygons.len(), edges.len())?;
+    end_line(w, &mut comments, Anchor::Counts)?;
+
+    for (i, v) in mesh.vertices.iter().enumerate() {
+        write_comments(w, &mut comments, Anchor::Vertex(i))?;
+        write!(w, "{} {} {}", v.pt.x, v.pt.y, v.pt.z)?;
+        if colored {
+            write_color(w, v.color.unwrap_or(Color::rgba(255, 255, 255, 255)))?;
+        }
+        end_line(w, &mut comments, Anchor::Vertex(i))?;
+    }
 
-    Some(())
+    for (i, (vertices, face)) in polygons.iter().enumerate() {
+        write_comments(w, &mut comments, Anchor::Face(i))?;
+        write!(w, "{}", vertices.len())?;
+        for v in vertices {
+            write!(w, " {}", v)?;
+        }
+        if let Some(c) = face.color {
+            write_color(w, c)?;
+        }
+        end_line(w, &mut comments, Anchor::Face(i))?;
+    }
+
+    for c in comments {
+        writeln!(w, "#{}", c.text)?;
+    }
+    Ok(())
 }
 
 /*
@@ -167,3 +565,186 @@ OFF
   4  3 2 6 7  0.000 0.700 0.300 0.75
   4  6 5 4 7  0.000 1.000 0.000 0.75
 */
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use super::*;
+
+    fn load(path: &Path) -> Mesh {
+        import(&mut File::open(path).unwrap())
+            .unwrap_or_else(|e| panic!("failed to import [{}]: {}", path.display(), e))
+    }
+
+    // The comments of a file, from their # to the end of their line.
+    fn comment_lines(data: &[u8]) -> Vec<String> {
+        String::from_utf8_lossy(data).lines()
+            .filter_map(|l| l.find('#').map(|i| l[i ..].trim_end().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn comments() {
+        let data = b"# before\nOFF # header\n# after header\n3 1 3 # counts\n\
+            # vertex 0\n0 0 0\n1 0 0 # vertex 1\n0 1 0\n# face 0\n3 0 1 2 # inline\n# end\n";
+        let mesh = import(&mut &data[..]).unwrap();
+        assert_eq!(mesh.comments[4], Comment { text: " vertex 0".to_string(), anchor: Anchor::Vertex(0), inline: false });
+        assert_eq!(mesh.comments[5], Comment { text: " vertex 1".to_string(), anchor: Anchor::Vertex(1), inline: true });
+        assert_eq!(mesh.comments[8].anchor, Anchor::End);
+
+        let mut copy = vec!();
+        export(&mut copy, &mesh).unwrap();
+        assert_eq!(comment_lines(data), comment_lines(&copy));
+        assert_eq!(import(&mut &copy[..]).unwrap().comments, mesh.comments);
+    }
+
+    fn positions(mesh: &Mesh) -> Vec<[f32; 3]> {
+        mesh.vertices.iter().map(|v| [v.pt.x, v.pt.y, v.pt.z]).collect()
+    }
+
+    #[test]
+    fn headers() {
+        let mesh = import(&mut &b"NOFF\n3 1 0\n0 0 0 0 0 2\n1 0 0 0 0 2\n0 1 0 0 0 2\n3 0 1 2\n"[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [0., 1., 0.]));
+        let n = mesh.normals[0][1];
+        assert_eq!([n.x, n.y, n.z], [0., 0., 1.]);
+
+        let mesh = import(&mut &b"STOFF 3 1 0\n0 0 0 0 0\n1 0 0 1 0.25\n0 1 0 0 1\n3 0 1 2\n"[..]).unwrap();
+        assert_eq!(positions(&mesh)[1], [1., 0., 0.]);
+        assert_eq!(mesh.vertices[1].uv, Some([1., 0.75]));
+        assert!(mesh.normals.is_empty());
+
+        // The fourth coordinate and the ones past the third are skipped.
+        let mesh = import(&mut &b"4OFF\n3 1 0\n0 0 0 1\n1 0 0 1\n0 1 0 1\n3 0 1 2\n"[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [0., 1., 0.]));
+        let mesh = import(&mut &b"nOFF\n5\n3 1 0\n0 0 0 7 7\n1 0 0 7 7\n0 1 0 7 7\n3 0 1 2\n"[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [0., 1., 0.]));
+        let mesh = import(&mut &b"4nOFF 3 3 1 0\n0 0 0 1\n1 0 0 1\n0 1 0 1\n3 0 1 2\n"[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [0., 1., 0.]));
+
+        assert!(matches!(import(&mut &b"# mesh\n\n  NOF 3 1 0\n"[..]),
+                         Err(ImportError::BadHeader { location: Location::Text { line: 3, column: 3 }, .. })));
+        assert!(matches!(import(&mut &b"NCOFF\n"[..]), Err(ImportError::BadHeader { .. })));
+    }
+
+    #[test]
+    fn colors() {
+        let data = b"OFF\n4 2 0\n0 0 0 255 0 0\n1 0 0 0 1 0 0.5\n1 1 0\n0 1 0 5\n4 0 1 2 3 10 20 30 40\n3 0 2 3 0\n";
+        let mesh = import(&mut &data[..]).unwrap();
+        let colors: Vec<_> = mesh.vertices.iter().map(|v| v.color).collect();
+        assert_eq!(colors, vec!(Some(Color::rgba(255, 0, 0, 255)), Some(Color::from_f32(0., 1., 0., 0.5)), None, None));
+        // The quad is split in two faces of the same color, the index in a
+        // color map of the last face is skipped.
+        let colors: Vec<_> = mesh.faces.iter().map(|f| f.color).collect();
+        assert_eq!(colors, vec!(Some(Color::rgba(10, 20, 30, 40)), Some(Color::rgba(10, 20, 30, 40)), None));
+
+        assert!(matches!(import(&mut &b"OFF\n3 1 0\n0 0 0\n1 0 0 1 0 red\n0 1 0\n3 0 1 2\n"[..]),
+                         Err(ImportError::Parse { location: Location::Text { line: 4, column: 7 }, .. })));
+        assert!(matches!(import(&mut &b"OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2 1 0.5 x\n"[..]),
+                         Err(ImportError::Parse { location: Location::Text { line: 6, column: 9 }, .. })));
+        assert!(matches!(import(&mut &b"OFF\n3 1 0\n0 0 0\n1 0 0\n0 1 0\n3 0 1 2 1 0 0 0 0\n"[..]),
+                         Err(ImportError::Parse { location: Location::Text { line: 6, column: 9 }, .. })));
+    }
+
+    #[test]
+    fn binary() {
+        let mut data = b"# binary\nCNOFF BINARY\n".to_vec();
+        let int = |data: &mut Vec<u8>, i: i32| data.extend(&i.to_be_bytes());
+        let float = |data: &mut Vec<u8>, f: f32| data.extend(&f.to_be_bytes());
+        for i in &[3, 1, 0] {
+            int(&mut data, *i);
+        }
+        for p in &[[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]] {
+            for c in p.iter().chain(&[0., 0., 1.]).chain(&[1., 0., 0., 1.]) {
+                float(&mut data, *c);
+            }
+        }
+        for i in &[3, 0, 1, 2, 3] {
+            int(&mut data, *i);
+        }
+        for c in &[0., 0., 1.] {
+            float(&mut data, *c);
+        }
+
+        let mesh = import(&mut &data[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [0., 1., 0.]));
+        assert_eq!(mesh.vertices[2].color, Some(Color::rgba(255, 0, 0, 255)));
+        let faces: Vec<_> = mesh.faces.iter().map(|f| ([f.a, f.b, f.c], f.color)).collect();
+        assert_eq!(faces, vec!(([0, 1, 2], Some(Color::rgba(0, 0, 255, 255)))));
+        let n = mesh.normals[0][2];
+        assert_eq!([n.x, n.y, n.z], [0., 0., 1.]);
+        assert_eq!(mesh.comments[0].text, " binary");
+
+        // Without the face color count, the data ends early.
+        let end = data.len() - 16;
+        assert!(matches!(import(&mut &data[.. end]),
+                         Err(ImportError::CountMismatch { location: Location::Byte(b), .. }) if b == end));
+        data[end - 4 .. end].copy_from_slice(&7i32.to_be_bytes());
+        assert!(matches!(import(&mut &data[..]),
+                         Err(ImportError::IndexOutOfRange { index: 7, .. })));
+    }
+
+    #[test]
+    fn inline_comments() {
+        let data = b"OFF#no space\n3 1 0 # counts\n0 0 0#first\n1 0 0\t# second\n0 1 0\n3 0 1 2 255 0 0 # red\n";
+        let mesh = import(&mut &data[..]).unwrap();
+        assert_eq!(mesh.faces[0].color, Some(Color::rgba(255, 0, 0, 255)));
+        let comments: Vec<_> = mesh.comments.iter().map(|c| (c.text.as_str(), c.anchor, c.inline)).collect();
+        assert_eq!(comments, vec!(
+            ("no space", Anchor::Header, true),
+            (" counts", Anchor::Counts, true),
+            ("first", Anchor::Vertex(0), true),
+            (" second", Anchor::Vertex(1), true),
+            (" red", Anchor::Face(0), true),
+        ));
+
+        let mut copy = vec!();
+        export(&mut copy, &mesh).unwrap();
+        assert_eq!(import(&mut &copy[..]).unwrap().comments, mesh.comments);
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("objects");
+        let tmp = std::env::temp_dir().join(format!("round_trip_{}.off", std::process::id()));
+
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("off") {
+                continue;
+            }
+            let mesh = load(&path);
+            let mut data = vec!();
+            export(&mut data, &mesh).unwrap();
+            fs::write(&tmp, &data).unwrap();
+            let copy = load(&tmp);
+
+            let name = path.display();
+            assert_eq!(comment_lines(&fs::read(&path).unwrap()), comment_lines(&data), "{}", name);
+            assert_eq!(mesh.comments, copy.comments, "{}", name);
+            assert_eq!(mesh.vertices.len(), copy.vertices.len(), "{}", name);
+            for (a, b) in mesh.vertices.iter().zip(&copy.vertices) {
+                assert_eq!([a.pt.x, a.pt.y, a.pt.z], [b.pt.x, b.pt.y, b.pt.z], "{}", name);
+                assert_eq!(a.color, b.color, "{}", name);
+            }
+            assert_eq!(mesh.polygons.len(), copy.polygons.len(), "{}", name);
+            for (a, b) in mesh.polygons.iter().zip(&copy.polygons) {
+                assert_eq!(a.vertices, b.vertices, "{}", name);
+                assert_eq!(a.faces, b.faces, "{}", name);
+            }
+            assert_eq!(mesh.faces.len(), copy.faces.len(), "{}", name);
+            for (a, b) in mesh.faces.iter().zip(&copy.faces) {
+                assert_eq!([a.a, a.b, a.c], [b.a, b.b, b.c], "{}", name);
+                assert_eq!(a.color, b.color, "{}", name);
+            }
+
+            // Writing the copy gives the same file again.
+            let mut again = vec!();
+            export(&mut again, &copy).unwrap();
+            assert_eq!(data, again, "{}", name);
+        }
+        let _ = fs::remove_file(&tmp);
+    }
+}
@@ -0,0 +1,713 @@
+use std::io::prelude::*;
+use std::io;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::vector::Vector3;
+use crate::mesh::*;
+use crate::io::error::{ImportError, Location, parse_token};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "ascii" => Ok(Format::Ascii),
+            "binary_little_endian" => Ok(Format::BinaryLittleEndian),
+            "binary_big_endian" => Ok(Format::BinaryBigEndian),
+            _ => Err(format!("unknown PLY format [{}]", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Scalar {
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        self == Scalar::F32 || self == Scalar::F64
+    }
+}
+
+enum PropertyType {
+    Scalar(Scalar),
+    // Count type and item type.
+    List(Scalar, Scalar),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| names.contains(&p.name.as_str()))
+    }
+
+    // Type of a scalar property, None for lists.
+    fn scalar(&self, index: usize) -> Option<Scalar> {
+        match self.properties[index].kind {
+            PropertyType::Scalar(s) => Some(s),
+            PropertyType::List(..) => None,
+        }
+    }
+}
+
+// Values of the body, whatever their encoding.
+struct Body<'a> {
+    data: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl<'a> Body<'a> {
+    // Location of an offset in the file, as a line and column in ASCII.
+    // Counting the lines is slow, so it is only done to report an error.
+    fn location(&self, pos: usize) -> Location {
+        if self.format != Format::Ascii {
+            return Location::Byte(pos);
+        }
+        let before = &self.data[.. pos.min(self.data.len())];
+        let line_start = before.iter().rposition(|c| *c == b'\n').map_or(0, |i| i + 1);
+        Location::Text {
+            line: before.iter().filter(|c| **c == b'\n').count() + 1,
+            column: pos - line_start + 1,
+        }
+    }
+
+    // Offset of the next value, to find its location in case of error.
+    fn next_offset(&mut self) -> usize {
+        if self.format == Format::Ascii {
+            while self.data.get(self.pos).is_some_and(|c| c.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+        }
+        self.pos
+    }
+
+    // Next value, None at the end of the data.
+    fn read(&mut self, t: Scalar) -> Result<Option<f64>, ImportError> {
+        if self.format == Format::Ascii {
+            while self.data.get(self.pos).is_some_and(|c| c.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.data.get(self.pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+                self.pos += 1;
+            }
+            if start == self.pos {
+                return Ok(None);
+            }
+            let token = String::from_utf8_lossy(&self.data[start .. self.pos]);
+            return match token.parse() {
+                Ok(v) => Ok(Some(v)),
+                Err(_) => Err(ImportError::parse(self.location(start), &token, "number")),
+            };
+        }
+
+        let bytes = match self.data.get(self.pos .. self.pos + t.size()) {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        self.pos += t.size();
+        let mut b = [0u8; 8];
+        b[.. t.size()].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            b[.. t.size()].reverse();
+        }
+        Ok(Some(match t {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        }))
+    }
+
+    // Record `index` of an element: each property is a list of values, with
+    // a single value for scalar properties.
+    fn record(&mut self, element: &Element, index: usize) -> Result<Vec<Vec<f64>>, ImportError> {
+        let truncated = ImportError::CountMismatch {
+            location: Location::EndOfFile,
+            what: match element.name.as_str() {
+                "vertex" => "vertices",
+                "face" => "faces",
+                _ => "records",
+            },
+            expected: element.count,
+            found: index,
+        };
+        let mut record = vec!();
+        for p in &element.properties {
+            let values = match p.kind {
+                PropertyType::Scalar(t) => match self.read(t)? {
+                    Some(v) => vec!(v),
+                    None => return Err(truncated),
+                },
+                PropertyType::List(count, item) => {
+                    let n = match self.read(count)? {
+                        Some(n) => n as usize,
+                        None => return Err(truncated),
+                    };
+                    let mut values = Vec::with_capacity(n.min(64));
+                    for _ in 0 .. n {
+                        match self.read(item)? {
+                            Some(v) => values.push(v),
+                            None => return Err(truncated),
+                        }
+                    }
+                    values
+                },
+            };
+            record.push(values);
+        }
+        Ok(record)
+    }
+}
+
+// Parse the header, returning the format, the elements and the offset of
+// the body.
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), ImportError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec!();
+    let mut pos = 0;
+    let mut line = 0;
+
+    loop {
+        let end = match data[pos ..].iter().position(|c| *c == b'\n') {
+            Some(end) => pos + end,
+            None => return Err(ImportError::parse(Location::EndOfFile, "", "end_header")),
+        };
+        let l = String::from_utf8_lossy(&data[pos .. end]);
+        pos = end + 1;
+        line += 1;
+        let tokens: Vec<&str> = l.split_whitespace().collect();
+        let parse_type = |t: &str| Scalar::parse(t)
+            .ok_or_else(|| ImportError::parse(Location::token(line, &l, t), t, "property type"));
+        let outside = || ImportError::parse(Location::token(line, &l, tokens[0]), "property", "property outside of an element");
+
+        match tokens.as_slice() {
+            ["ply"] if format.is_none() && elements.is_empty() => {},
+            ["format", f, _version] => match f.parse() {
+                Ok(f) => format = Some(f),
+                Err(_) => return Err(ImportError::BadHeader {
+                    location: Location::token(line, &l, f),
+                    token: f.to_string(),
+                }),
+            },
+            ["comment", ..] | ["obj_info", ..] | [] => {},
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: parse_token(line, &l, count, "element count")?,
+                properties: vec!(),
+            }),
+            ["property", "list", count, item, name] => {
+                let kind = PropertyType::List(parse_type(count)?, parse_type(item)?);
+                elements.last_mut().ok_or_else(outside)?.properties.push(Property { name: name.to_string(), kind });
+            },
+            ["property", t, name] => {
+                let kind = PropertyType::Scalar(parse_type(t)?);
+                elements.last_mut().ok_or_else(outside)?.properties.push(Property { name: name.to_string(), kind });
+            },
+            ["end_header"] => break,
+            _ => return Err(ImportError::parse(Location::token(line, &l, tokens[0]), &l, "header line")),
+        }
+    }
+
+    match format {
+        Some(format) => Ok((format, elements, pos)),
+        None => Err(ImportError::parse(Location::Unknown, "", "format line")),
+    }
+}
+
+// Color from up to four components, integers in [0, 255] or floats in [0, 1].
+fn color(c: &[f64], float: bool) -> Color {
+    let a = c.get(3).cloned().unwrap_or(if float { 1. } else { 255. });
+    if float {
+        Color::from_f32(c[0] as f32, c[1] as f32, c[2] as f32, a as f32)
+    } else {
+        Color::rgba(c[0] as u8, c[1] as u8, c[2] as u8, a as u8)
+    }
+}
+
+// Indices of the color properties of an element and whether they are floats,
+// None when the element has no color. Colors must be scalar properties.
+fn color_properties(element: &Element) -> Result<Option<(Vec<usize>, bool)>, ImportError> {
+    let mut indices: Vec<usize> = vec!();
+    for names in &[&["red", "r", "diffuse_red"][..], &["green", "g", "diffuse_green"],
+                   &["blue", "b", "diffuse_blue"]] {
+        match element.property(names) {
+            Some(i) => indices.push(i),
+            None => return Ok(None),
+        }
+    }
+    if let Some(alpha) = element.property(&["alpha", "a"]) {
+        indices.push(alpha);
+    }
+    if let Some(i) = indices.iter().find(|i| element.scalar(**i).is_none()) {
+        return Err(ImportError::Unsupported {
+            location: Location::Unknown,
+            what: format!("list color property [{}] of element [{}]", element.properties[*i].name, element.name),
+        });
+    }
+    let float = element.scalar(indices[0]).is_some_and(|t| t.is_float());
+    Ok(Some((indices, float)))
+}
+
+// Import an ASCII or binary PLY file. Vertices are read from the vertex
+// element with their normals, colors and texture coordinates, and faces from
+// the vertex_indices (or vertex_index) list of the face element. Other
+// elements are skipped, and files without faces load as point clouds.
+pub fn import<R: Read>(r: &mut R) -> Result<Mesh, ImportError> {
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+    if !data.starts_with(b"ply") {
+        let first = data.split(|c| c.is_ascii_whitespace()).next().unwrap_or(&[]);
+        return Err(ImportError::BadHeader {
+            location: Location::Text { line: 1, column: 1 },
+            token: String::from_utf8_lossy(first).into_owned(),
+        });
+    }
+
+    let mut mesh = Mesh::new();
+    let (format, elements, start) = parse_header(&data)?;
+    let mut body = Body { data: &data, pos: start, format };
+    let mut vertex_normals: Vec<Vector3> = vec!();
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let coords: Vec<usize> = ["x", "y", "z"].iter()
+                    .map(|n| element.property(&[n]))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| ImportError::Unsupported {
+                        location: Location::Unknown,
+                        what: "vertices without coordinates".to_string(),
+                    })?;
+                let normal: Option<Vec<usize>> = ["nx", "ny", "nz"].iter()
+                    .map(|n| element.property(&[n]))
+                    .collect();
+                let colors = color_properties(element)?;
+                let uv = element.property(&["u", "s", "texture_u", "texture_s"])
+                    .zip(element.property(&["v", "t", "texture_v", "texture_t"]));
+
+                for i in 0 .. element.count {
+                    let r = body.record(element, i)?;
+                    let get = |i: usize| r[i].first().cloned().unwrap_or(0.) as f32;
+
+                    if let Some(n) = &normal {
+                        vertex_normals.push(Vector3::new(get(n[0]), get(n[1]), get(n[2])));
+                    }
+                    let color = colors.as_ref().map(|(c, float)| {
+                        let c: Vec<f64> = c.iter().map(|i| get(*i) as f64).collect();
+                        color(&c, *float)
+                    });
+                    mesh.vertices.push(Vertex {
+                        pt: Vector3::new(get(coords[0]), get(coords[1]), get(coords[2])),
+                        color,
+                        // PLY puts v = 0 at the bottom of the image.
+                        uv: uv.map(|(u, v)| [get(u), 1. - get(v)]),
+                    });
+                }
+            },
+            "face" => {
+                let indices = element.property(&["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| ImportError::Unsupported {
+                        location: Location::Unknown,
+                        what: "faces without vertex indices".to_string(),
+                    })?;
+                let colors = color_properties(element)?;
+
+                for i in 0 .. element.count {
+                    let start = body.next_offset();
+                    let r = body.record(element, i)?;
+                    if r[indices].len() < 3 {
+                        return Err(ImportError::CountMismatch {
+                            location: body.location(start),
+                            what: "face vertices",
+                            expected: 3,
+                            found: r[indices].len(),
+                        });
+                    }
+                    let mut verts = vec!();
+                    for v in &r[indices] {
+                        let v = *v as i64;
+                        if v < 0 || v as usize >= mesh.vertices.len() {
+                            return Err(ImportError::IndexOutOfRange {
+                                location: body.location(start),
+                                index: v,
+                                count: mesh.vertices.len(),
+                            });
+                        }
+                        verts.push(v as usize);
+                    }
+                    let color = colors.as_ref().map(|(c, float)| {
+                        let c: Vec<f64> = c.iter().map(|i| r[*i][0]).collect();
+                        color(&c, *float)
+                    });
+
+                    for f in 0 .. verts.len() - 2 {
+                        mesh.faces.push(Face { color, ..Face::new(verts[0], verts[f + 1], verts[f + 2]) });
+                    }
+                }
+            },
+            _ => {
+                for i in 0 .. element.count {
+                    body.record(element, i)?;
+                }
+            },
+        }
+    }
+
+    if vertex_normals.len() == mesh.vertices.len() && !mesh.faces.is_empty() {
+        mesh.normals = mesh.faces.iter()
+            .map(|f| [vertex_normals[f.a].normalize(), vertex_normals[f.b].normalize(),
+                      vertex_normals[f.c].normalize()])
+            .collect();
+    }
+
+    Ok(mesh)
+}
+
+// Write the vertices with their normals, colors and texture coordinates when
+// the mesh has them, and the faces with their colors. A vertex shared by
+// faces with different normals gets the normal of its first face corner.
+pub fn export<W: Write>(w: &mut W, mesh: &Mesh, format: Format) -> io::Result<()> {
+    let normals = if mesh.has_normals() {
+        // Going backwards, the first corner of each vertex is written last.
+        let mut normals = vec![Vector3::zero(); mesh.vertices.len()];
+        for (f, n) in mesh.faces.iter().zip(&mesh.normals).rev() {
+            for (v, n) in [f.a, f.b, f.c].iter().zip(n) {
+                normals[*v] = *n;
+            }
+        }
+        Some(normals)
+    } else {
+        None
+    };
+    let vertex_colors = mesh.vertices.iter().any(|v| v.color.is_some());
+    let uvs = mesh.vertices.iter().any(|v| v.uv.is_some());
+    let face_colors = mesh.faces.iter().any(|f| f.color.is_some());
+
+    let name = match format {
+        Format::Ascii => "ascii",
+        Format::BinaryLittleEndian => "binary_little_endian",
+        Format::BinaryBigEndian => "binary_big_endian",
+    };
+    writeln!(w, "ply")?;
+    writeln!(w, "format {} 1.0", name)?;
+    writeln!(w, "element vertex {}", mesh.vertices.len())?;
+    for p in &["x", "y", "z"] {
+        writeln!(w, "property float {}", p)?;
+    }
+    if normals.is_some() {
+        for p in &["nx", "ny", "nz"] {
+            writeln!(w, "property float {}", p)?;
+        }
+    }
+    if vertex_colors {
+        for p in &["red", "green", "blue", "alpha"] {
+            writeln!(w, "property uchar {}", p)?;
+        }
+    }
+    if uvs {
+        writeln!(w, "property float u")?;
+        writeln!(w, "property float v")?;
+    }
+    writeln!(w, "element face {}", mesh.faces.len())?;
+    writeln!(w, "property list uchar int vertex_indices")?;
+    if face_colors {
+        for p in &["red", "green", "blue", "alpha"] {
+            writeln!(w, "property uchar {}", p)?;
+        }
+    }
+    writeln!(w, "end_header")?;
+
+    let mut out = Writer { w, format, first: true };
+    for (i, v) in mesh.vertices.iter().enumerate() {
+        out.floats(&[v.pt.x, v.pt.y, v.pt.z])?;
+        if let Some(normals) = &normals {
+            out.floats(&[normals[i].x, normals[i].y, normals[i].z])?;
+        }
+        if vertex_colors {
+            let c = v.color.unwrap_or(Color::WHITE);
+            out.bytes(&[c.r, c.g, c.b, c.a])?;
+        }
+        if uvs {
+            let [tu, tv] = v.uv.unwrap_or([0., 0.]);
+            out.floats(&[tu, 1. - tv])?;
+        }
+        out.end_record()?;
+    }
+    for f in &mesh.faces {
+        out.bytes(&[3])?;
+        out.ints(&[f.a as i32, f.b as i32, f.c as i32])?;
+        if face_colors {
+            let c = f.color.unwrap_or(Color::WHITE);
+            out.bytes(&[c.r, c.g, c.b, c.a])?;
+        }
+        out.end_record()?;
+    }
+    Ok(())
+}
+
+// Values of the body in the chosen encoding, one record per line in ASCII.
+struct Writer<'a, W: Write> {
+    w: &'a mut W,
+    format: Format,
+    first: bool,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+    fn separator(&mut self) -> io::Result<()> {
+        if !self.first {
+            write!(self.w, " ")?;
+        }
+        self.first = false;
+        Ok(())
+    }
+
+    fn floats(&mut self, values: &[f32]) -> io::Result<()> {
+        for v in values {
+            match self.format {
+                Format::Ascii => {
+                    self.separator()?;
+                    write!(self.w, "{}", v)?;
+                },
+                Format::BinaryLittleEndian => self.w.write_all(&v.to_le_bytes())?,
+                Format::BinaryBigEndian => self.w.write_all(&v.to_be_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn ints(&mut self, values: &[i32]) -> io::Result<()> {
+        for v in values {
+            match self.format {
+                Format::Ascii => {
+                    self.separator()?;
+                    write!(self.w, "{}", v)?;
+                },
+                Format::BinaryLittleEndian => self.w.write_all(&v.to_le_bytes())?,
+                Format::BinaryBigEndian => self.w.write_all(&v.to_be_bytes())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn bytes(&mut self, values: &[u8]) -> io::Result<()> {
+        for v in values {
+            match self.format {
+                Format::Ascii => {
+                    self.separator()?;
+                    write!(self.w, "{}", v)?;
+                },
+                _ => self.w.write_all(&[*v])?,
+            }
+        }
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> io::Result<()> {
+        self.first = true;
+        if self.format == Format::Ascii {
+            writeln!(self.w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\nformat {} 1.0\ncomment made by hand\nelement vertex 4\n\
+        property float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\n\
+        property uchar blue\nelement face 1\nproperty list uchar int vertex_indices\n\
+        property short flags\nproperty double quality\nend_header\n";
+
+    const POSITIONS: [[f32; 3]; 4] = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.5]];
+
+    fn import_str(data: &str) -> Result<Mesh, ImportError> {
+        import(&mut data.as_bytes())
+    }
+
+    // The quad of HEADER in a binary encoding, with a different color for
+    // each vertex.
+    fn binary(format: &str, big_endian: bool) -> Vec<u8> {
+        let mut data = HEADER.replace("{}", format).into_bytes();
+        let mut push = |bytes: &[u8]| {
+            let mut bytes = bytes.to_vec();
+            if big_endian {
+                bytes.reverse();
+            }
+            data.extend(bytes);
+        };
+        for (i, p) in POSITIONS.iter().enumerate() {
+            for c in p {
+                push(&c.to_le_bytes());
+            }
+            push(&[i as u8 * 10]);
+            push(&[i as u8 * 10 + 1]);
+            push(&[i as u8 * 10 + 2]);
+        }
+        push(&[4]);
+        for i in 0 .. 4i32 {
+            push(&i.to_le_bytes());
+        }
+        push(&(-3i16).to_le_bytes());
+        push(&0.5f64.to_le_bytes());
+        data
+    }
+
+    fn check_quad(mesh: &Mesh) {
+        assert_eq!(mesh.vertices.len(), 4);
+        for (i, (v, p)) in mesh.vertices.iter().zip(&POSITIONS).enumerate() {
+            assert_eq!([v.pt.x, v.pt.y, v.pt.z], *p);
+            let c = i as u8 * 10;
+            assert_eq!(v.color, Some(Color::rgba(c, c + 1, c + 2, 255)));
+        }
+        let faces: Vec<[usize; 3]> = mesh.faces.iter().map(|f| [f.a, f.b, f.c]).collect();
+        assert_eq!(faces, vec!([0, 1, 2], [0, 2, 3]));
+    }
+
+    #[test]
+    fn ascii() {
+        let body = "0 0 0 0 1 2\n1 0 0 10 11 12\n  1 1 0\n20 21 22\n0 1 0.5 30 31 32\n4 0 1 2 3 -3 0.5\n";
+        check_quad(&import_str(&(HEADER.replace("{}", "ascii") + body)).unwrap());
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        check_quad(&import(&mut &binary("binary_little_endian", false)[..]).unwrap());
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        check_quad(&import(&mut &binary("binary_big_endian", true)[..]).unwrap());
+    }
+
+    #[test]
+    fn round_trip() {
+        let body = "0 0 0 0 1 2\n1 0 0 10 11 12\n1 1 0 20 21 22\n0 1 0.5 30 31 32\n4 0 1 2 3 -3 0.5\n";
+        let mesh = import_str(&(HEADER.replace("{}", "ascii") + body)).unwrap();
+        for format in &[Format::Ascii, Format::BinaryLittleEndian, Format::BinaryBigEndian] {
+            let mut data = vec!();
+            export(&mut data, &mesh, *format).unwrap();
+            check_quad(&import(&mut &data[..]).unwrap());
+        }
+    }
+
+    #[test]
+    fn point_cloud() {
+        let data = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\n\
+            property float z\nproperty float nx\nproperty float ny\nproperty float nz\nend_header\n\
+            0 0 0 0 0 1\n1 2 3 0 1 0\n";
+        let mesh = import_str(data).unwrap();
+        assert_eq!(mesh.vertices.len(), 2);
+        assert_eq!([mesh.vertices[1].pt.x, mesh.vertices[1].pt.y, mesh.vertices[1].pt.z], [1., 2., 3.]);
+        assert!(mesh.faces.is_empty());
+        assert!(mesh.normals.is_empty());
+
+        let empty = data.replace("end_header", "element face 0\nproperty list uchar int vertex_indices\nend_header");
+        let mesh = import_str(&empty).unwrap();
+        assert_eq!((mesh.vertices.len(), mesh.faces.len()), (2, 0));
+    }
+
+    #[test]
+    fn bad_headers() {
+        let vertex = "element vertex 1\nproperty float x\nproperty float y\nproperty float z\n";
+        let error = |header: &str| match import_str(&format!("{}\n0 0 0\n", header)) {
+            Ok(_) => panic!("imported bad header [{}]", header),
+            Err(e) => e,
+        };
+
+        assert!(matches!(error("PLY\nformat ascii 1.0\nend_header"),
+                         ImportError::BadHeader { location: Location::Text { line: 1, column: 1 }, .. }));
+        assert!(matches!(error("ply\nformat binary_middle_endian 1.0\nend_header"),
+                         ImportError::BadHeader { location: Location::Text { line: 2, column: 8 }, .. }));
+        assert!(matches!(error("ply\nend_header"), ImportError::Parse { location: Location::Unknown, .. }));
+        assert!(matches!(import_str(&format!("ply\nformat ascii 1.0\n{}", vertex)),
+                         Err(ImportError::Parse { location: Location::EndOfFile, expected: "end_header", .. })));
+        assert!(matches!(error("ply\nformat ascii 1.0\nproperty float x\nend_header"),
+                         ImportError::Parse { location: Location::Text { line: 3, column: 1 }, .. }));
+        assert!(matches!(error("ply\nformat ascii 1.0\nelement vertex many\nend_header"),
+                         ImportError::Parse { location: Location::Text { line: 3, column: 16 }, .. }));
+        assert!(matches!(error(&format!("ply\nformat ascii 1.0\n{}property float16 w\nend_header", vertex)),
+                         ImportError::Parse { location: Location::Text { line: 7, column: 10 }, .. }));
+        assert!(matches!(error(&format!("ply\nformat ascii 1.0\n{}verbatim\nend_header", vertex)),
+                         ImportError::Parse { location: Location::Text { line: 7, column: 1 }, .. }));
+        assert!(matches!(error("ply\nformat ascii 1.0\nelement vertex 1\nend_header"),
+                         ImportError::Unsupported { .. }));
+    }
+
+    #[test]
+    fn list_colors() {
+        let data = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+            property list uchar uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n3 0 1 2 0 0 0\n";
+        assert!(matches!(import_str(data), Err(ImportError::Unsupported { .. })));
+    }
+
+    #[test]
+    fn bad_bodies() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+            property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        let error = |body: &str| match import_str(&(header.to_string() + body)) {
+            Ok(_) => panic!("imported bad body [{}]", body),
+            Err(e) => e,
+        };
+
+        assert!(matches!(error("0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n"),
+                         ImportError::IndexOutOfRange { location: Location::Text { line: 13, column: 1 }, index: 3, .. }));
+        assert!(matches!(error("0 0 0\n1 0 0\n0 1 0\n  2 0 1\n"),
+                         ImportError::CountMismatch { location: Location::Text { line: 13, column: 3 }, .. }));
+        assert!(matches!(error("0 0 0\n1 zero 0\n"),
+                         ImportError::Parse { location: Location::Text { line: 11, column: 3 }, .. }));
+        assert!(matches!(error("0 0 0\n1 0 0\n0 1 0\n"),
+                         ImportError::CountMismatch { location: Location::EndOfFile, what: "faces", .. }));
+    }
+}
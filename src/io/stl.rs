@@ -0,0 +1,363 @@
+use std::io::prelude::*;
+use std::io;
+use std::collections::HashMap;
+
+use crate::vector::Vector3;
+use crate::mesh::*;
+use crate::io::error::{ImportError, Location, parse_token};
+
+// Size of the header and of each triangle record of binary files.
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+// Merges the copies of each vertex that STL files store for every triangle.
+// Only exact duplicates are welded.
+struct Welder {
+    indices: HashMap<[u32; 3], usize>,
+}
+
+impl Welder {
+    fn new() -> Welder {
+        Welder { indices: HashMap::new() }
+    }
+
+    fn vertex(&mut self, mesh: &mut Mesh, pt: Vector3) -> usize {
+        // Adding 0 turns -0 into 0, so that both are welded together.
+        let pt = Vector3::new(pt.x + 0., pt.y + 0., pt.z + 0.);
+        let key = [pt.x.to_bits(), pt.y.to_bits(), pt.z.to_bits()];
+        *self.indices.entry(key).or_insert_with(|| {
+            mesh.vertices.push(Vertex { pt, color: None, uv: None });
+            mesh.vertices.len() - 1
+        })
+    }
+}
+
+// Add a triangle of the file, with its facet normal.
+fn push_triangle(mesh: &mut Mesh, welder: &mut Welder, normals: &mut Vec<[Vector3; 3]>,
+                 normal: Vector3, pts: [Vector3; 3]) {
+    let a = welder.vertex(mesh, pts[0]);
+    let b = welder.vertex(mesh, pts[1]);
+    let c = welder.vertex(mesh, pts[2]);
+    mesh.faces.push(Face::new(a, b, c));
+    normals.push([normal; 3]);
+}
+
+// Import an ASCII or binary STL file. Binary files are recognized by their
+// size, since some of them also start with "solid". The facet normals are
+// kept as the normals of the faces, unless some of them are missing.
+pub fn import<R: Read>(r: &mut R) -> Result<Mesh, ImportError> {
+    let mut data = vec!();
+    r.read_to_end(&mut data)?;
+    let mut mesh = Mesh::new();
+
+    let binary_size = data.get(HEADER_SIZE .. HEADER_SIZE + 4)
+        .map(|n| HEADER_SIZE + 4 + TRIANGLE_SIZE * u32::from_le_bytes([n[0], n[1], n[2], n[3]]) as usize);
+    let mut normals = vec!();
+
+    if binary_size == Some(data.len()) || !data.starts_with(b"solid") {
+        import_binary(&data, &mut mesh, &mut normals)?;
+    } else {
+        let ascii = import_ascii(&String::from_utf8_lossy(&data), &mut mesh, &mut normals);
+        // Binary data with a header starting with "solid", whose size does
+        // not match its triangle count, is not loaded as an empty mesh.
+        if mesh.faces.is_empty() && std::str::from_utf8(&data).is_err() {
+            return Err(match binary_size {
+                Some(size) => ImportError::CountMismatch {
+                    location: Location::Byte(HEADER_SIZE),
+                    what: "bytes for the triangle count",
+                    expected: size,
+                    found: data.len(),
+                },
+                None => ImportError::BadHeader { location: Location::Byte(0), token: "solid".to_string() },
+            });
+        }
+        ascii?;
+    }
+
+    // Zero normals mean that they were left for the reader to compute.
+    if normals.iter().all(|n| n[0].norm() > 0.) {
+        mesh.normals = normals.iter()
+            .map(|n| [n[0].normalize(); 3])
+            .collect();
+    }
+
+    Ok(mesh)
+}
+
+fn import_binary(data: &[u8], mesh: &mut Mesh, normals: &mut Vec<[Vector3; 3]>) -> Result<(), ImportError> {
+    if data.len() < HEADER_SIZE + 4 {
+        return Err(ImportError::CountMismatch {
+            location: Location::Byte(0),
+            what: "header bytes",
+            expected: HEADER_SIZE + 4,
+            found: data.len(),
+        });
+    }
+    let count = &data[HEADER_SIZE .. HEADER_SIZE + 4];
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+
+    let records = &data[HEADER_SIZE + 4 ..];
+    if records.len() / TRIANGLE_SIZE < count {
+        return Err(ImportError::CountMismatch {
+            location: Location::EndOfFile,
+            what: "triangles",
+            expected: count,
+            found: records.len() / TRIANGLE_SIZE,
+        });
+    }
+
+    let mut welder = Welder::new();
+    for record in records.chunks_exact(TRIANGLE_SIZE).take(count) {
+        // Normal and three vertices as little-endian floats, followed by
+        // two attribute bytes which are ignored.
+        let v: Vec<Vector3> = record[.. 48].chunks_exact(12)
+            .map(|c| {
+                let f = |i: usize| f32::from_le_bytes([c[i], c[i + 1], c[i + 2], c[i + 3]]);
+                Vector3::new(f(0), f(4), f(8))
+            })
+            .collect();
+        push_triangle(mesh, &mut welder, normals, v[0], [v[1], v[2], v[3]]);
+    }
+
+    Ok(())
+}
+
+// solid name
+//   facet normal nx ny nz
+//     outer loop
+//       vertex x y z (three times)
+//     endloop
+//   endfacet
+// endsolid name
+fn import_ascii(text: &str, mesh: &mut Mesh, normals: &mut Vec<[Vector3; 3]>) -> Result<(), ImportError> {
+    let mut welder = Welder::new();
+    let mut normal = Vector3::zero();
+    let mut pts = vec!();
+
+    for (line, l) in text.lines().enumerate() {
+        let line = line + 1;
+        let tokens: Vec<&str> = l.split_whitespace().collect();
+        // The three values after the keywords.
+        let coords = |first: usize| -> Result<Vector3, ImportError> {
+            if tokens.len() != first + 3 {
+                return Err(ImportError::CountMismatch {
+                    location: Location::Text { line, column: 1 },
+                    what: "coordinates",
+                    expected: 3,
+                    found: tokens.len().saturating_sub(first),
+                });
+            }
+            let c = |i: usize| parse_token::<f32>(line, l, tokens[first + i], "coordinate");
+            Ok(Vector3::new(c(0)?, c(1)?, c(2)?))
+        };
+
+        match tokens.first() {
+            Some(&"facet") => {
+                normal = match tokens.get(1) {
+                    Some(&"normal") => coords(2)?,
+                    _ => Vector3::zero(),
+                };
+                pts.clear();
+            },
+            Some(&"vertex") => pts.push(coords(1)?),
+            Some(&"endfacet") => {
+                if pts.len() != 3 {
+                    return Err(ImportError::CountMismatch {
+                        location: Location::token(line, l, tokens[0]),
+                        what: "facet vertices",
+                        expected: 3,
+                        found: pts.len(),
+                    });
+                }
+                push_triangle(mesh, &mut welder, normals, normal, [pts[0], pts[1], pts[2]]);
+            },
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+// Normal written for a face: the one loaded with the mesh if there is one,
+// the geometric normal otherwise.
+fn facet_normal(mesh: &Mesh, i: usize) -> Vector3 {
+    if mesh.has_normals() {
+        let [a, b, c] = mesh.normals[i];
+        let n = a + b + c;
+        if n.norm() > 0. {
+            return n.normalize();
+        }
+    }
+    mesh.face_normal(&mesh.faces[i]).unwrap_or_else(Vector3::zero)
+}
+
+pub fn export_ascii<W: Write>(w: &mut W, mesh: &Mesh, name: &str) -> io::Result<()> {
+    writeln!(w, "solid {}", name)?;
+    for (i, face) in mesh.faces.iter().enumerate() {
+        let n = facet_normal(mesh, i);
+        writeln!(w, "  facet normal {:e} {:e} {:e}", n.x, n.y, n.z)?;
+        writeln!(w, "    outer loop")?;
+        for v in &[face.a, face.b, face.c] {
+            let p = mesh.vertices[*v].pt;
+            writeln!(w, "      vertex {:e} {:e} {:e}", p.x, p.y, p.z)?;
+        }
+        writeln!(w, "    endloop")?;
+        writeln!(w, "  endfacet")?;
+    }
+    writeln!(w, "endsolid {}", name)
+}
+
+pub fn export_binary<W: Write>(w: &mut W, mesh: &Mesh) -> io::Result<()> {
+    // The header must not start with "solid", to not be taken for ASCII.
+    let mut header = [b' '; HEADER_SIZE];
+    let title = b"binary STL";
+    header[.. title.len()].copy_from_slice(title);
+    w.write_all(&header)?;
+    w.write_all(&(mesh.faces.len() as u32).to_le_bytes())?;
+
+    for (i, face) in mesh.faces.iter().enumerate() {
+        let mut record = Vec::with_capacity(TRIANGLE_SIZE);
+        let n = facet_normal(mesh, i);
+        let pts = [n, mesh.vertices[face.a].pt, mesh.vertices[face.b].pt, mesh.vertices[face.c].pt];
+        for p in &pts {
+            for c in &[p.x, p.y, p.z] {
+                record.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        record.extend_from_slice(&[0, 0]);
+        w.write_all(&record)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+
+    use super::*;
+
+    const ASCII: &str = "solid square\n  facet normal 0 0 2\n    outer loop\n      vertex 0 0 0\n\
+        \x20     vertex 1 0 0\n      vertex 1 1 0\n    endloop\n  endfacet\n  facet normal 0 0 1\n\
+        \x20   outer loop\n      vertex 0 0 -0\n      vertex 1 1 0\n      vertex 0 1 0\n    endloop\n\
+        \x20 endfacet\nendsolid square\n";
+
+    // Binary file with the given header and triangles, a normal followed by
+    // three vertices.
+    fn binary(header: &[u8], triangles: &[[[f32; 3]; 4]]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[.. header.len()].copy_from_slice(header);
+        data.extend(&(triangles.len() as u32).to_le_bytes());
+        for t in triangles {
+            for c in t.iter().flatten() {
+                data.extend(&c.to_le_bytes());
+            }
+            data.extend(&[0, 0]);
+        }
+        data
+    }
+
+    fn positions(mesh: &Mesh) -> Vec<[f32; 3]> {
+        mesh.vertices.iter().map(|v| [v.pt.x, v.pt.y, v.pt.z]).collect()
+    }
+
+    fn faces(mesh: &Mesh) -> Vec<[usize; 3]> {
+        mesh.faces.iter().map(|f| [f.a, f.b, f.c]).collect()
+    }
+
+    #[test]
+    fn ascii() {
+        let mesh = import(&mut ASCII.as_bytes()).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]));
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [0, 2, 3]));
+        let n = mesh.normals[0][0];
+        assert_eq!([n.x, n.y, n.z], [0., 0., 1.]);
+
+        // Without a normal for every facet, none is kept.
+        let mesh = import(&mut ASCII.replace("facet normal 0 0 1", "facet").as_bytes()).unwrap();
+        assert_eq!(mesh.faces.len(), 2);
+        assert!(mesh.normals.is_empty());
+
+        let missing = ASCII.replace("      vertex 0 1 0\n", "");
+        assert!(matches!(import(&mut missing.as_bytes()),
+                         Err(ImportError::CountMismatch { location: Location::Text { line: 14, column: 3 }, found: 2, .. })));
+        let bad = ASCII.replace("vertex 1 0 0", "vertex 1 0");
+        assert!(matches!(import(&mut bad.as_bytes()),
+                         Err(ImportError::CountMismatch { location: Location::Text { line: 5, column: 1 }, found: 2, .. })));
+    }
+
+    #[test]
+    fn binary_data() {
+        let triangles = [
+            [[0., 0., 1.], [0., 0., 0.], [1., 0., 0.], [1., 1., 0.]],
+            [[0., 0., 1.], [0., 0., 0.], [1., 1., 0.], [0., 1., 0.]],
+        ];
+        let mesh = import(&mut &binary(b"square", &triangles)[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]));
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [0, 2, 3]));
+        assert_eq!(mesh.normals.len(), 2);
+
+        let data = binary(b"square", &triangles);
+        assert!(matches!(import(&mut &data[.. data.len() - 1]),
+                         Err(ImportError::CountMismatch { location: Location::EndOfFile, expected: 2, found: 1, .. })));
+        assert!(matches!(import(&mut &data[.. 50]),
+                         Err(ImportError::CountMismatch { location: Location::Byte(0), .. })));
+    }
+
+    #[test]
+    fn binary_with_solid_header() {
+        // Binary files are recognized by their size, whatever their header.
+        let triangles = [[[0., 0., 1.], [0., 0., 0.], [1., 0., 0.], [1., 1., 0.]]];
+        let data = binary(b"solid made by a careless exporter", &triangles);
+        let mesh = import(&mut &data[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [1., 1., 0.]));
+
+        // With a wrong size, they are not taken for an empty ASCII file.
+        let mut data = binary(b"solid", &triangles);
+        data.extend(&[0xff; 10]);
+        assert!(matches!(import(&mut &data[..]),
+                         Err(ImportError::CountMismatch { location: Location::Byte(80), expected: 134, found: 144, .. })));
+    }
+
+    #[test]
+    fn welding() {
+        // Only exact copies are welded, along with zeros of both signs.
+        let triangles = [
+            [[0., 0., 1.], [0., 0., 0.], [1., 0., 0.], [0., 1., 0.]],
+            [[0., 0., 1.], [-0., 0., -0.], [1., 0., 0.], [1e-7, 1., 0.]],
+        ];
+        let mesh = import(&mut &binary(b"", &triangles)[..]).unwrap();
+        assert_eq!(positions(&mesh), vec!([0., 0., 0.], [1., 0., 0.], [0., 1., 0.], [1e-7, 1., 0.]));
+        assert_eq!(faces(&mesh), vec!([0, 1, 2], [0, 1, 3]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("objects").join("teapot.off");
+        let mesh = crate::io::off::import(&mut File::open(path).unwrap()).unwrap();
+        let corners = |mesh: &Mesh| -> Vec<[f32; 3]> {
+            mesh.faces.iter()
+                .flat_map(|f| vec!(f.a, f.b, f.c))
+                .map(|v| mesh.vertices[v].pt)
+                .map(|p| [p.x, p.y, p.z])
+                .collect()
+        };
+
+        let mut ascii = vec!();
+        export_ascii(&mut ascii, &mesh, "teapot").unwrap();
+        let mut binary = vec!();
+        export_binary(&mut binary, &mesh).unwrap();
+        assert!(ascii.starts_with(b"solid teapot\n"));
+        assert!(!binary.starts_with(b"solid"));
+
+        for data in &[ascii, binary] {
+            let copy = import(&mut &data[..]).unwrap();
+            assert_eq!(corners(&mesh), corners(&copy));
+            assert_eq!(copy.normals.len(), mesh.faces.len());
+            for (f, n) in mesh.faces.iter().zip(&copy.normals) {
+                let expected = mesh.face_normal(f).unwrap();
+                assert!((n[0] - expected).norm() < 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// Four lanes of floats, one for each pixel of a 2x2 quad. With the simd
+// feature they map to SIMD registers, otherwise the lanes are computed one
+// after the other. Both give the same results, the lanes being plain IEEE
+// operations either way.
+// Comparisons return a mask with bit i set when lane i passes.
+
+use std::ops::{Add, Sub, Mul, Div};
+
+#[cfg(feature = "simd")]
+use wide::{f32x4, f64x4, CmpGe, CmpGt, CmpLt};
+
+#[derive(Copy, Clone)]
+pub struct F32x4(
+    #[cfg(feature = "simd")] f32x4,
+    #[cfg(not(feature = "simd"))] [f32; 4],
+);
+
+// Used for edge functions, which are integers too large for 32 bits but
+// exact in a double.
+#[derive(Copy, Clone)]
+pub struct F64x4(
+    #[cfg(feature = "simd")] f64x4,
+    #[cfg(not(feature = "simd"))] [f64; 4],
+);
+
+#[cfg(feature = "simd")]
+impl F32x4 {
+    #[inline]
+    pub fn new(lanes: [f32; 4]) -> F32x4 {
+        F32x4(f32x4::new(lanes))
+    }
+
+    #[inline]
+    pub fn splat(v: f32) -> F32x4 {
+        F32x4(f32x4::splat(v))
+    }
+
+    #[inline]
+    pub fn to_array(self) -> [f32; 4] {
+        self.0.to_array()
+    }
+
+    #[inline]
+    pub fn lt(self, other: F32x4) -> u8 {
+        self.0.cmp_lt(other.0).move_mask() as u8
+    }
+
+    #[inline]
+    pub fn gt(self, other: F32x4) -> u8 {
+        self.0.cmp_gt(other.0).move_mask() as u8
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+impl F32x4 {
+    #[inline]
+    pub fn new(lanes: [f32; 4]) -> F32x4 {
+        F32x4(lanes)
+    }
+
+    #[inline]
+    pub fn splat(v: f32) -> F32x4 {
+        F32x4([v; 4])
+    }
+
+    #[inline]
+    pub fn to_array(self) -> [f32; 4] {
+        self.0
+    }
+
+    #[inline]
+    pub fn lt(self, other: F32x4) -> u8 {
+        mask(|i| self.0[i] < other.0[i])
+    }
+
+    #[inline]
+    pub fn gt(self, other: F32x4) -> u8 {
+        mask(|i| self.0[i] > other.0[i])
+    }
+}
+
+#[cfg(feature = "simd")]
+impl F64x4 {
+    #[inline]
+    pub fn new(lanes: [f64; 4]) -> F64x4 {
+        F64x4(f64x4::new(lanes))
+    }
+
+    #[inline]
+    pub fn splat(v: f64) -> F64x4 {
+        F64x4(f64x4::splat(v))
+    }
+
+    #[inline]
+    pub fn ge(self, other: F64x4) -> u8 {
+        self.0.cmp_ge(other.0).move_mask() as u8
+    }
+
+    // Lanes rounded to the nearest float.
+    #[inline]
+    pub fn to_f32(self) -> F32x4 {
+        let [a, b, c, d] = self.0.to_array();
+        F32x4::new([a as f32, b as f32, c as f32, d as f32])
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+impl F64x4 {
+    #[inline]
+    pub fn new(lanes: [f64; 4]) -> F64x4 {
+        F64x4(lanes)
+    }
+
+    #[inline]
+    pub fn splat(v: f64) -> F64x4 {
+        F64x4([v; 4])
+    }
+
+    #[inline]
+    pub fn ge(self, other: F64x4) -> u8 {
+        mask(|i| self.0[i] >= other.0[i])
+    }
+
+    #[inline]
+    pub fn to_f32(self) -> F32x4 {
+        F32x4(self.0.map(|v| v as f32))
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+fn mask<F: Fn(usize) -> bool>(lane: F) -> u8 {
+    (0 .. 4).filter(|&i| lane(i)).fold(0, |m, i| m | 1 << i)
+}
+
+macro_rules! lane_ops {
+    ($t:ident, $($op:ident $f:ident $sym:tt $assign:tt),*) => {
+        $(
+            impl $op for $t {
+                type Output = $t;
+
+                #[cfg(feature = "simd")]
+                #[inline]
+                fn $f(self, other: $t) -> $t {
+                    $t(self.0 $sym other.0)
+                }
+
+                #[cfg(not(feature = "simd"))]
+                #[inline]
+                fn $f(self, other: $t) -> $t {
+                    let mut out = self.0;
+                    for (o, b) in out.iter_mut().zip(other.0.iter()) {
+                        *o $assign *b;
+                    }
+                    $t(out)
+                }
+            }
+        )*
+    }
+}
+
+lane_ops!(F32x4, Add add + +=, Sub sub - -=, Mul mul * *=, Div div / /=);
+lane_ops!(F64x4, Add add + +=);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lanes with special values: zeros of both signs, infinities, NaN,
+    // subnormals and values which round when converted to f32.
+    const F32_LANES: [[f32; 4]; 4] = [
+        [0., -0., 1.5, -2.25],
+        [f32::INFINITY, f32::NEG_INFINITY, f32::NAN, 1e-40],
+        [3.4e38, -1e-3, 7., 0.1],
+        [1., 0., -0., f32::MIN_POSITIVE],
+    ];
+    const F64_LANES: [[f64; 4]; 3] = [
+        [0., -0., 1e12 + 0.5, -(1i64 << 40) as f64],
+        [f64::INFINITY, f64::NAN, 1e300, 0.1],
+        [16777217., -3., 2.5, 1e-310],
+    ];
+
+    // Same value, NaN being equal to NaN.
+    fn same_f32(a: f32, b: f32) -> bool {
+        a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+    }
+
+    fn scalar_mask<F: Fn(usize) -> bool>(lane: F) -> u8 {
+        (0 .. 4).filter(|&i| lane(i)).fold(0, |m, i| m | 1 << i)
+    }
+
+    #[test]
+    fn f32_lanes_match_scalar_operations() {
+        for a in &F32_LANES {
+            assert_eq!(F32x4::new(*a).to_array().map(f32::to_bits), a.map(f32::to_bits));
+            assert_eq!(F32x4::splat(a[2]).to_array().map(f32::to_bits), [a[2].to_bits(); 4]);
+
+            for b in &F32_LANES {
+                let (va, vb) = (F32x4::new(*a), F32x4::new(*b));
+                let scalar = |op: fn(f32, f32) -> f32| [0, 1, 2, 3].map(|l| op(a[l], b[l]));
+                let ops = [
+                    (va + vb, scalar(|x, y| x + y)),
+                    (va - vb, scalar(|x, y| x - y)),
+                    (va * vb, scalar(|x, y| x * y)),
+                    (va / vb, scalar(|x, y| x / y)),
+                ];
+                for (i, (lanes, expected)) in ops.iter().enumerate() {
+                    let lanes = lanes.to_array();
+                    for l in 0 .. 4 {
+                        assert!(same_f32(lanes[l], expected[l]), "op {} on {} and {}", i, a[l], b[l]);
+                    }
+                }
+                assert_eq!(va.lt(vb), scalar_mask(|i| a[i] < b[i]));
+                assert_eq!(va.gt(vb), scalar_mask(|i| a[i] > b[i]));
+            }
+        }
+    }
+
+    #[test]
+    fn f64_lanes_match_scalar_operations() {
+        for a in &F64_LANES {
+            let va = F64x4::new(*a);
+            let rounded = va.to_f32().to_array();
+            for l in 0 .. 4 {
+                assert!(same_f32(rounded[l], a[l] as f32), "{}", a[l]);
+            }
+
+            for b in &F64_LANES {
+                let vb = F64x4::new(*b);
+                let sum = (va + vb).to_f32().to_array();
+                for l in 0 .. 4 {
+                    assert!(same_f32(sum[l], (a[l] + b[l]) as f32), "{} + {}", a[l], b[l]);
+                }
+                assert_eq!(va.ge(vb), scalar_mask(|i| a[i] >= b[i]));
+                assert_eq!(F64x4::splat(b[0]).ge(va), scalar_mask(|i| b[0] >= a[i]));
+            }
+        }
+    }
+}
@@ -0,0 +1,27 @@
+// Software rasterizer: meshes are loaded from OFF, OBJ, STL, PLY or glTF
+// files and rendered into a framebuffer, which can be saved as an image or,
+// with the viewer feature, shown in an SFML window.
+
+#[cfg(feature = "viewer")]
+extern crate sfml;
+
+pub mod io;
+pub mod mesh;
+pub mod renderer;
+#[macro_use]
+pub mod vector;
+pub mod matrix;
+pub mod camera;
+pub mod light;
+pub mod color;
+pub mod framebuffer;
+pub mod clipping;
+pub mod varyings;
+pub mod lanes;
+pub mod shadow;
+pub mod material;
+pub mod texture;
+pub mod scene;
+pub mod tiles;
+#[cfg(feature = "viewer")]
+pub mod viewer;
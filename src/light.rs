@@ -1,15 +1,167 @@
+use std::str::FromStr;
+
 use crate::vector::Vector3;
-use sfml::graphics::Color;
+use crate::color::Color;
+
+// Distance attenuation of point and spot lights, the received intensity is
+// divided by constant + linear * d + quadratic * d^2.
+#[derive(Copy, Clone)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    // Physically based falloff, with the intensity given at distance 1.
+    pub fn inverse_square() -> Attenuation {
+        Attenuation { constant: 0., linear: 0., quadratic: 1. }
+    }
+
+    pub fn factor(&self, d: f32) -> f32 {
+        1. / (self.constant + self.linear * d + self.quadratic * d * d).max(f32::EPSILON)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum LightKind {
+    // Infinitely far away, the direction is the one the light travels in.
+    Directional { dir: Vector3 },
+    Point { pos: Vector3, attenuation: Attenuation },
+    // A point light restricted to a cone of half-angle `angle` (in radians)
+    // around `dir`. The intensity fades out over the outer `falloff` fraction
+    // of the cone, 0 gives a hard edge.
+    Spot { pos: Vector3, dir: Vector3, angle: f32, falloff: f32, attenuation: Attenuation },
+}
 
+#[derive(Copy, Clone)]
 pub struct Light {
-    pub dir: Vector3,
-//  pub color: Color,
-//  pub intensity: f32,
+    pub kind: LightKind,
+    pub color: Color,
+    pub intensity: f32,
 }
+
 impl Light {
+    // White directional light.
     pub fn new(x: f32, y: f32, z: f32) -> Light {
+        Light::directional(Vector3::new(x,y,z), Color::WHITE, 1.)
+    }
+
+    pub fn directional(dir: Vector3, color: Color, intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Directional { dir: dir.normalize() },
+            color,
+            intensity,
+        }
+    }
+
+    pub fn point(pos: Vector3, color: Color, intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Point { pos, attenuation: Attenuation::inverse_square() },
+            color,
+            intensity,
+        }
+    }
+
+    pub fn spot(pos: Vector3, dir: Vector3, angle: f32, color: Color, intensity: f32) -> Light {
         Light {
-            dir : Vector3::new(x,y,z).normalize()
+            kind: LightKind::Spot {
+                pos,
+                dir: dir.normalize(),
+                angle,
+                falloff: 0.2,
+                attenuation: Attenuation::inverse_square(),
+            },
+            color,
+            intensity,
+        }
+    }
+
+    // Unit vector from the point towards the light, and the intensity of the
+    // light reaching the point, to be multiplied by its color. None if the
+    // point is not lit.
+    pub fn incident(&self, p: Vector3) -> Option<(Vector3, f32)> {
+        match self.kind {
+            LightKind::Directional { dir } => Some((-dir, self.intensity)),
+            LightKind::Point { pos, attenuation } => {
+                let (l, d) = towards(p, pos)?;
+                Some((l, self.intensity * attenuation.factor(d)))
+            },
+            LightKind::Spot { pos, dir, angle, falloff, attenuation } => {
+                let (l, d) = towards(p, pos)?;
+                let cos = -l.dot(&dir);
+                let outer = angle.cos();
+                let inner = (angle * (1. - falloff)).cos();
+                if cos <= outer {
+                    return None;
+                }
+                let t = ((cos - outer) / (inner - outer).max(f32::EPSILON)).min(1.);
+                let cone = t * t * (3. - 2. * t);
+                Some((l, self.intensity * attenuation.factor(d) * cone))
+            },
+        }
+    }
+}
+
+// Unit vector and distance from p to q.
+fn towards(p: Vector3, q: Vector3) -> Option<(Vector3, f32)> {
+    let v = q - p;
+    let d = v.length();
+    if d > 0. { Some((v / d, d)) } else { None }
+}
+
+pub fn parse_vec(s: &str) -> Result<Vector3, String> {
+    let c: Vec<f32> = s.split(',')
+        .map(|t| t.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid vector [{}]", s))?;
+    if c.len() != 3 {
+        return Err(format!("invalid vector [{}]", s));
+    }
+    Ok(Vector3::new(c[0], c[1], c[2]))
+}
+
+// Light description used on the command line, the color components are in
+// [0, 1] and the angle is in degrees:
+//   directional:DX,DY,DZ[:R,G,B[:INTENSITY]]
+//   point:X,Y,Z[:R,G,B[:INTENSITY]]
+//   spot:X,Y,Z:DX,DY,DZ:ANGLE[:R,G,B[:INTENSITY]]
+impl FromStr for Light {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Light, String> {
+        let fields: Vec<&str> = s.split(':').collect();
+        let nr_params = match fields[0] {
+            "directional" | "point" => 1,
+            "spot" => 3,
+            _ => return Err(format!("unknown light type [{}]", fields[0])),
+        };
+        if fields.len() < nr_params + 1 || fields.len() > nr_params + 3 {
+            return Err(format!("invalid light [{}]", s));
         }
+
+        let color = match fields.get(nr_params + 1) {
+            Some(c) => {
+                let c = parse_vec(c)?;
+                Color::from_f32(c.x, c.y, c.z, 1.)
+            },
+            None => Color::WHITE,
+        };
+        let intensity = match fields.get(nr_params + 2) {
+            Some(i) => i.parse::<f32>().map_err(|_| format!("invalid intensity [{}]", i))?,
+            None => 1.,
+        };
+
+        let v = parse_vec(fields[1])?;
+        Ok(match fields[0] {
+            "directional" => Light::directional(v, color, intensity),
+            "point" => Light::point(v, color, intensity),
+            _ => {
+                let dir = parse_vec(fields[2])?;
+                let angle = fields[3].parse::<f32>()
+                    .map_err(|_| format!("invalid angle [{}]", fields[3]))?;
+                Light::spot(v, dir, angle.to_radians(), color, intensity)
+            },
+        })
     }
 }
@@ -1,116 +1,150 @@
-extern crate sfml;
-
-mod io;
-mod mesh;
-mod renderer;
-#[macro_use]
-mod vector;
-mod matrix;
-mod camera;
-mod light;
-
-use io::off::import;
+mod cli;
+
+use rusterizer::io::{off, stl, ply};
+use rusterizer::io::format::Format;
+use rusterizer::io::image;
 use std::fs::File;
+use std::path::Path;
+use std::io::{BufWriter, Write};
 use std::env;
+use std::process;
 
-use sfml::graphics::{RenderWindow, RenderTarget};
-use sfml::window::{Event, Key, mouse::Button, Style, mouse::Wheel};
-use sfml::system::Vector2i;
-
-use renderer::*;
-use mesh::*;
-use camera::Camera;
-use vector::Vector3;
-use light::Light;
+use rusterizer::renderer::*;
+use rusterizer::mesh::*;
+use rusterizer::camera::Camera;
+use rusterizer::texture::Texture;
+use rusterizer::framebuffer::Framebuffer;
+#[cfg(feature = "viewer")]
+use rusterizer::viewer;
+use cli::{Command, RenderOptions};
 
 use std::f32;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let args: Vec<_> = env::args().collect();
-    let mut mesh_file = "";
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(msg) => {
+            eprintln!("{}\n{}", msg, cli::USAGE);
+            process::exit(2);
+        }
+    };
 
-    if args.len() == 1 {
-        print!("Expected OBJ file path as argument\n");
-        return;
-    } else {
-        mesh_file = &args[1]
+    match command {
+        Command::View { mesh_file, crease_angle } => {
+            if let Err(msg) = view(&mesh_file, crease_angle) {
+                eprintln!("{}", msg);
+                process::exit(1);
+            }
+        }
+        Command::Render(opts) => {
+            if let Err(msg) = render_to_file(&opts) {
+                eprintln!("{}", msg);
+                process::exit(1);
+            }
+        }
+        Command::Convert { mesh_file, output, ascii } => {
+            if let Err(msg) = load_mesh(&mesh_file).and_then(|mesh| save_mesh(&mesh, &output, ascii)) {
+                eprintln!("{}", msg);
+                process::exit(1);
+            }
+        }
     }
+}
 
-    let mut mesh = Mesh::new();
-    let mut file = File::open(mesh_file).unwrap();
+// The format is chosen from the extension, or from the content for unknown
+// extensions and for the standard input, read when the file is "-".
+fn load_mesh(mesh_file: &str) -> Result<Mesh, String> {
+    let mesh = if mesh_file == "-" {
+        Mesh::from_reader(std::io::stdin().lock(), Format::Auto)
+    } else {
+        Mesh::load(mesh_file)
+    }.map_err(|e| format!("Failed to import mesh from file {}: {}", mesh_file, e))?;
 
-    let res = import(&mut file, &mut mesh);
-    if res == None {
-        eprint!("Failed to import mesh from file {}\n", mesh_file);
-        return;
+    for w in &mesh.warnings {
+        eprintln!("warning: {}", w);
     }
+    println!("loaded {} vertices and {} faces", mesh.vertices.len(), mesh.faces.len());
+    for g in &mesh.groups {
+        println!("group [{}] [{}]: {} faces", g.object, g.name, g.faces.len());
+    }
+    Ok(mesh)
+}
+
+// The format is chosen from the extension, ascii selects the text variant
+// of formats having both.
+fn save_mesh(mesh: &Mesh, output: &str, ascii: bool) -> Result<(), String> {
+    let path = Path::new(output);
+    let ext = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let file = File::create(path)
+        .map_err(|e| format!("Failed to create {}: {}", output, e))?;
+    let mut w = BufWriter::new(file);
+
+    let written = match ext.as_deref() {
+        Some("stl") if ascii => {
+            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("mesh");
+            stl::export_ascii(&mut w, mesh, name)
+        },
+        Some("stl") => stl::export_binary(&mut w, mesh),
+        Some("ply") if ascii => ply::export(&mut w, mesh, ply::Format::Ascii),
+        Some("ply") => ply::export(&mut w, mesh, ply::Format::BinaryLittleEndian),
+        Some("off") => off::export(&mut w, mesh),
+        _ => return Err(format!("Unsupported mesh format [{}]", output)),
+    };
+    written.and_then(|_| w.flush())
+        .map_err(|e| format!("Failed to write {}: {}", output, e))
+}
 
-    let width: u32 = 800;
-    let height: u32 = 600;
+// Headless mode: render a single frame and write it to an image file.
+fn render_to_file(opts: &RenderOptions) -> Result<(), String> {
+    let mut mesh = load_mesh(&opts.mesh_file)?;
+    if !mesh.has_normals() {
+        mesh.compute_normals(NormalWeighting::Angle, opts.crease_angle.to_radians());
+    }
 
-    // Rotate the mesh and translate it
-    mesh.translate(Vector3::new(0.,0.,-12.));
-    mesh.rot_x(-f32::consts::PI/2.);
+    if let Some(path) = &opts.texture {
+        let texture = Texture::load(path)
+            .map_err(|e| format!("Failed to load texture {}: {}", path, e))?;
+        mesh.material.texture = Some(mesh.textures.len());
+        mesh.textures.push(texture);
+    }
+    for texture in &mut mesh.textures {
+        texture.filter = opts.filter.unwrap_or(texture.filter);
+        if let Some(wrap) = opts.wrap {
+            texture.wrap_u = wrap;
+            texture.wrap_v = wrap;
+        }
+    }
 
     let mut camera = Camera::new();
-    let mut window = RenderWindow::new(
-        (width, height),
-        "Dot",
-        Style::CLOSE,
-        &Default::default(),
-    );
-    window.set_vertical_sync_enabled(true);
-    window.set_framerate_limit(60);
-    window.set_mouse_position(&Vector2i::new(width as i32 / 2, height as i32 / 2));
-
-    let light = Light::new(1.,-1.,-1.);
-
-    // Create empty Z-buffer
-    let mut zbuf = vec![std::f32::MAX; (width * height) as usize];
-
-    let mut paused = false;
-    let mut mouse_left = false;
-    let mut rotate = false;
-    let mut prev_mp = Vector2i::new(0, 0);
-
-    loop {
-        while let Some(event) = window.poll_event() {
-            match event {
-                Event::Closed | Event::KeyPressed { code: Key::Escape, .. } =>
-                    return,
-                Event::KeyPressed { code: Key::Space, .. } =>
-                    paused = !paused,
-                Event::MouseButtonPressed { button: Button::Left, x, y } => {
-                    prev_mp.x = x;
-                    prev_mp.y = y;
-                    rotate = true
-                },
-                Event::MouseButtonReleased { button: Button::Left, .. } =>
-                    rotate = false,
-                Event::MouseWheelScrolled {wheel: Wheel::Vertical, delta, ..} =>
-                    mesh.translate(Vector3::new(0.,0.,delta)),
-                _ => {},
-            }
-        }
+    camera.aspect = opts.width as f32 / opts.height as f32;
 
-        if paused {
-            continue
-        }
+    // Same orientation as the viewer, but framed to fit the whole mesh.
+    mesh.rot_x(-f32::consts::PI/2.);
+    mesh.fit_view(&mut camera);
 
-        if rotate {
-            let mp = window.mouse_position();
-            mesh.rot_y((mp.x - prev_mp.x) as f32 * 0.005);
-            mesh.rot_x((mp.y - prev_mp.y) as f32 * 0.005);
-            prev_mp = mp
-        }
+    let mut fb = Framebuffer::new(opts.width, opts.height);
+    render(&mut fb, &mesh, &camera, &opts.scene, &opts.settings);
 
-        // Clear the Z-buffer.
-        for c in zbuf.iter_mut() {
-            *c = std::f32::MAX;
-        }
+    image::save(&opts.output, &fb)
+        .map_err(|e| format!("Failed to write {}: {}", opts.output, e))
+}
 
-        render_shadow(&mut window, &mut zbuf, &mesh, &camera, &light);
-        window.display();
+#[cfg(feature = "viewer")]
+fn view(mesh_file: &str, crease_angle: f32) -> Result<(), String> {
+    let mut mesh = load_mesh(mesh_file)?;
+    if !mesh.has_normals() {
+        mesh.compute_normals(NormalWeighting::Angle, crease_angle.to_radians());
     }
+    viewer::run(mesh);
+    Ok(())
+}
+
+// Fails before loading the mesh, which could not be shown.
+#[cfg(not(feature = "viewer"))]
+fn view(_mesh_file: &str, _crease_angle: f32) -> Result<(), String> {
+    Err("rusterizer was built without the viewer feature, rebuild it with --features viewer or use the render command".to_string())
 }
@@ -0,0 +1,46 @@
+use crate::color::Color;
+
+// Surface description. The Blinn-Phong modes use the diffuse, specular,
+// shininess and ambient terms, the physically based mode uses the diffuse
+// color as base color along with the metallic, roughness and emissive terms.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Material {
+    pub diffuse: Color,
+    // Color of the highlights, black for a matte surface.
+    pub specular: Color,
+    // Exponent of the highlights, higher values give smaller and sharper ones.
+    pub shininess: f32,
+    // Reflectance to the ambient light, on top of the diffuse color.
+    pub ambient: Color,
+    // 0 for a dielectric, 1 for a metal.
+    pub metallic: f32,
+    // Perceptual roughness in [0, 1], 0 being a perfect mirror.
+    pub roughness: f32,
+    // Light emitted by the surface itself, in sRGB.
+    pub emissive: Color,
+    // Index in Mesh::textures of an image multiplying the diffuse color.
+    pub texture: Option<usize>,
+}
+
+impl Material {
+    pub fn new(diffuse: Color, specular: Color, shininess: f32) -> Material {
+        Material {
+            diffuse,
+            specular,
+            shininess,
+            ambient: Color::WHITE,
+            metallic: 0.,
+            roughness: 0.5,
+            emissive: Color::BLACK,
+            texture: None,
+        }
+    }
+}
+
+impl Default for Material {
+    // Light gray plastic with soft highlights, leaving some headroom below
+    // white for them.
+    fn default() -> Material {
+        Material::new(Color::rgb(204, 204, 204), Color::rgb(77, 77, 77), 32.)
+    }
+}
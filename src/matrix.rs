@@ -4,6 +4,7 @@ use std::ops::Mul;
 use crate::vector::Vector3;
 use crate::camera::Camera;
 
+#[derive(Copy, Clone)]
 pub struct Matrix4 {
     pub cells: [[f32; 4]; 4],
 }
@@ -87,20 +88,96 @@ impl Matrix4 {
         out
     }
 
+    // Apply only the linear part of the transform, for directions and normals.
+    pub fn transform_dir(&self, v: Vector3) -> Vector3 {
+        let c = &self.cells;
+        Vector3 {
+            x: v.x * c[0][0] + v.y * c[0][1] + v.z * c[0][2],
+            y: v.x * c[1][0] + v.y * c[1][1] + v.z * c[1][2],
+            z: v.x * c[2][0] + v.y * c[2][1] + v.z * c[2][2],
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut out = Matrix4::zero();
+        for i in 0..4 {
+            for j in 0..4 {
+                out.cells[i][j] = self.cells[j][i];
+            }
+        }
+        out
+    }
+
+    // Determinant of the linear part of the transform, negative when it
+    // mirrors space.
+    pub fn determinant(&self) -> f32 {
+        let c = &self.cells;
+        c[0][0] * (c[1][1] * c[2][2] - c[1][2] * c[2][1])
+            - c[0][1] * (c[1][0] * c[2][2] - c[1][2] * c[2][0])
+            + c[0][2] * (c[1][0] * c[2][1] - c[1][1] * c[2][0])
+    }
+
+    // Inverse transpose of the linear part, which keeps normals perpendicular
+    // to the surface under non-uniform scaling. Singular transforms use the
+    // cofactor matrix instead.
+    pub fn normal_matrix(&self) -> Matrix4 {
+        let c = &self.cells;
+        let mut out = Matrix4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+                let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+                out.cells[i][j] = c[i1][j1] * c[i2][j2] - c[i1][j2] * c[i2][j1];
+            }
+        }
+        let det = self.determinant();
+        if det != 0. {
+            for row in out.cells.iter_mut().take(3) {
+                for v in row.iter_mut().take(3) {
+                    *v /= det;
+                }
+            }
+        }
+        out
+    }
+
     pub fn project(cam: &Camera) -> Matrix4 {
         let f = cam.far;
         let n = cam.near;
         let s = 1. / f32::tan(cam.fov * PI / 360.);
+        // The field of view is vertical, the horizontal one follows the aspect ratio.
+        let sx = s / cam.aspect;
 
         // After perspective divide (vector /z), the depth coodinate z
         // is remapped to (near => 0), (far => 1)
         matrix![
-            s,  0., 0.,           0.;
+            sx, 0., 0.,           0.;
             0., s,  0.,           0.;
             0., 0., -(f+n)/(f-n), -2.*f*n/(f-n);
             0., 0., -1.,          0.
         ]
     }
+
+    // Orthographic projection of the box [l, r] x [b, t] x [-n, -f], looking
+    // down the -z axis like the perspective projection.
+    pub fn orthographic(l: f32, r: f32, b: f32, t: f32, n: f32, f: f32) -> Matrix4 {
+        matrix![
+            2./(r-l), 0.,       0.,        -(r+l)/(r-l);
+            0.,       2./(t-b), 0.,        -(t+b)/(t-b);
+            0.,       0.,       -2./(f-n), -(f+n)/(f-n);
+            0.,       0.,       0.,        1.
+        ]
+    }
+
+    // Change of basis to the frame (x, y, z), the vectors must be orthonormal.
+    pub fn from_basis(x: Vector3, y: Vector3, z: Vector3) -> Matrix4 {
+        matrix![
+            x.x, x.y, x.z, 0.;
+            y.x, y.y, y.z, 0.;
+            z.x, z.y, z.z, 0.;
+            0.,  0.,  0.,  1.
+        ]
+    }
 }
 
 impl Mul<Matrix4> for Matrix4 {
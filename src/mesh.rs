@@ -1,14 +1,24 @@
 
+use std::ops::Range;
+use std::io::Read;
+use std::fs::File;
+use std::path::Path;
+
 use crate::vector::Vector3;
 use crate::matrix::Matrix4;
 use crate::camera::Camera;
-use std::f64;
 
-use sfml::graphics::Color;
+use crate::color::Color;
+use crate::material::Material;
+use crate::texture::Texture;
+use crate::io::error::{ImportError, ImportWarning};
+use crate::io::format::{self, Format};
 
 pub struct Vertex {
     pub pt: Vector3,
     pub color: Option<Color>,
+    // Texture coordinates, v = 0 being the top of the image.
+    pub uv: Option<[f32; 2]>,
 }
 
 pub struct Face {
@@ -16,27 +26,113 @@ pub struct Face {
     pub b: usize,
     pub c: usize,
     pub color: Option<Color>,
+    // Index in Mesh::materials, the mesh material is used when None.
+    pub material: Option<usize>,
+}
+
+impl Face {
+    // Face without a color of its own, using the mesh material.
+    pub fn new(a: usize, b: usize, c: usize) -> Face {
+        Face { a, b, c, color: None, material: None }
+    }
+
+    pub fn with_material(a: usize, b: usize, c: usize, material: Option<usize>) -> Face {
+        Face { material, ..Face::new(a, b, c) }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NormalWeighting {
+    // Faces contribute proportionally to their area.
+    Area,
+    // Faces contribute proportionally to the angle of their corner at the
+    // vertex, which does not depend on how the surface is tessellated.
+    Angle,
+}
+
+// Angle between two faces above which their shared edge is kept sharp, in
+// degrees. corner_normals takes it in radians.
+pub const DEFAULT_CREASE_ANGLE_DEGREES: f32 = 60.;
+
+// Named range of faces, from the objects and groups of OBJ files or the
+// nodes of glTF scenes.
+pub struct Group {
+    pub object: String,
+    pub name: String,
+    pub faces: Range<usize>,
+}
+
+// Polygon of the source file, which was split into a fan of faces.
+pub struct Polygon {
+    pub vertices: Vec<usize>,
+    pub faces: Range<usize>,
+}
+
+// Element of the file a comment belongs to. Elements come in this order in
+// the file, vertices and faces being numbered from 0.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Anchor {
+    Header,
+    Counts,
+    Vertex(usize),
+    Face(usize),
+    // After the last face.
+    End,
+}
+
+// Comment of the file, without its leading #. It is either on its own line
+// before the element, or inline at the end of the line of the element.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Comment {
+    pub text: String,
+    pub anchor: Anchor,
+    pub inline: bool,
 }
 
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub faces: Vec<Face>,
+    // Smooth normals at the three corners of each face, see compute_normals.
+    pub normals: Vec<[Vector3; 3]>,
+    // Material of the faces that do not have their own.
+    pub material: Material,
+    pub materials: Vec<Material>,
+    // Referenced by the materials.
+    pub textures: Vec<Texture>,
+    pub groups: Vec<Group>,
+    // Polygons and comments of the file, kept by the OFF importer so that
+    // the mesh can be written back unchanged.
+    pub polygons: Vec<Polygon>,
+    pub comments: Vec<Comment>,
+    // Problems found by the importer which did not stop it.
+    pub warnings: Vec<ImportWarning>,
     rot_x: f32,
     rot_y: f32,
     rot_z: f32,
     pub translation: Vector3,
 }
 
-macro_rules! face {
-    ($a:expr, $b:expr, $c:expr) => (Face { a: $a, b: $b, c: $c, color: None })
-}
 
 
+impl Default for Mesh {
+    fn default() -> Mesh {
+        Mesh::new()
+    }
+}
+
 impl Mesh {
     pub fn new() -> Mesh {
         Mesh {
             vertices: Vec::new(),
             faces: Vec::new(),
+            normals: Vec::new(),
+            material: Material::default(),
+            materials: Vec::new(),
+            textures: Vec::new(),
+            groups: Vec::new(),
+            polygons: Vec::new(),
+            comments: Vec::new(),
+            warnings: Vec::new(),
             rot_x: 0.,
             rot_y: 0.,
             rot_z: 0.,
@@ -44,6 +140,20 @@ impl Mesh {
         }
     }
 
+    // Load a mesh file, which can be gzip compressed. The format is chosen
+    // from the extension, or from the content if the extension is unknown.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Mesh, ImportError> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        format::import(&mut File::open(path)?, Format::from_path(path), dir)
+    }
+
+    // Read a mesh from memory, a pipe or any other source. Files referenced
+    // by the mesh are looked up in the current directory.
+    pub fn from_reader<R: Read>(mut r: R, format: Format) -> Result<Mesh, ImportError> {
+        format::import(&mut r, format, Path::new(""))
+    }
+
     fn pt(&self, index: usize) -> Vector3 {
         self.vertices[index].pt
     }
@@ -68,4 +178,138 @@ impl Mesh {
         Matrix4::rot_and_translate(self.rot_x, self.rot_y, self.rot_z,
             self.translation)
     }
+
+    pub fn face_material(&self, face: &Face) -> &Material {
+        face.material.map_or(&self.material, |i| &self.materials[i])
+    }
+
+    // Unit normal of a face, None if the face is degenerate.
+    pub fn face_normal(&self, face: &Face) -> Option<Vector3> {
+        let n = (self.pt(face.b) - self.pt(face.a)).cross(&(self.pt(face.c) - self.pt(face.a)));
+        let len = n.length();
+        if len > 0. { Some(n / len) } else { None }
+    }
+
+    // Contribution of each corner of a face to the normal of its vertex.
+    fn corner_weights(&self, face: &Face, weighting: NormalWeighting) -> [f32; 3] {
+        let (a, b, c) = (self.pt(face.a), self.pt(face.b), self.pt(face.c));
+        match weighting {
+            NormalWeighting::Area => {
+                let area = (b - a).cross(&(c - a)).length() / 2.;
+                [area; 3]
+            },
+            NormalWeighting::Angle => {
+                fn angle(o: Vector3, p: Vector3, q: Vector3) -> f32 {
+                    let (u, v) = (p - o, q - o);
+                    let cos = u.dot(&v) / (u.length() * v.length());
+                    if cos.is_nan() { 0. } else { cos.clamp(-1., 1.).acos() }
+                }
+                [angle(a, b, c), angle(b, c, a), angle(c, a, b)]
+            },
+        }
+    }
+
+    // One smooth normal per vertex, averaged over all the adjacent faces.
+    pub fn vertex_normals(&self, weighting: NormalWeighting) -> Vec<Vector3> {
+        let mut normals = vec![Vector3::zero(); self.vertices.len()];
+
+        for face in &self.faces {
+            if let Some(n) = self.face_normal(face) {
+                let w = self.corner_weights(face, weighting);
+                normals[face.a] = normals[face.a] + n * w[0];
+                normals[face.b] = normals[face.b] + n * w[1];
+                normals[face.c] = normals[face.c] + n * w[2];
+            }
+        }
+
+        normals.iter()
+            .map(|n| if n.norm() > 0. { n.normalize() } else { *n })
+            .collect()
+    }
+
+    // Smooth normals at each face corner. Adjacent faces only contribute to
+    // the normal when the angle between both faces is below the crease angle
+    // (in radians), so that hard edges stay sharp.
+    pub fn corner_normals(&self, weighting: NormalWeighting, crease_angle: f32) -> Vec<[Vector3; 3]> {
+        let face_normals: Vec<Option<Vector3>> = self.faces.iter()
+            .map(|f| self.face_normal(f))
+            .collect();
+        let weights: Vec<[f32; 3]> = self.faces.iter()
+            .map(|f| self.corner_weights(f, weighting))
+            .collect();
+
+        // Faces around each vertex, with the corner of the vertex in the face.
+        let mut adjacent: Vec<Vec<(usize, usize)>> = vec![Vec::new(); self.vertices.len()];
+        for (i, f) in self.faces.iter().enumerate() {
+            adjacent[f.a].push((i, 0));
+            adjacent[f.b].push((i, 1));
+            adjacent[f.c].push((i, 2));
+        }
+
+        let min_cos = crease_angle.cos();
+        self.faces.iter().enumerate().map(|(i, f)| {
+            let n = match face_normals[i] {
+                Some(n) => n,
+                None => return [Vector3::zero(); 3],
+            };
+            let mut corners = [Vector3::zero(); 3];
+            for (k, v) in [f.a, f.b, f.c].iter().enumerate() {
+                let mut sum = Vector3::zero();
+                for &(j, corner) in &adjacent[*v] {
+                    if let Some(m) = face_normals[j] {
+                        if j == i || n.dot(&m) >= min_cos {
+                            sum = sum + m * weights[j][corner];
+                        }
+                    }
+                }
+                corners[k] = if sum.norm() > 0. { sum.normalize() } else { n };
+            }
+            corners
+        }).collect()
+    }
+
+    // Keep the normals already set, loaded from the file for instance.
+    pub fn has_normals(&self) -> bool {
+        !self.faces.is_empty() && self.normals.len() == self.faces.len()
+    }
+
+    pub fn compute_normals(&mut self, weighting: NormalWeighting, crease_angle: f32) {
+        self.normals = self.corner_normals(weighting, crease_angle);
+    }
+
+    // Axis aligned bounding box of the vertices, in object space.
+    pub fn bounds(&self) -> Option<(Vector3, Vector3)> {
+        let first = self.vertices.first()?.pt;
+        let (mut min, mut max) = (first, first);
+        for v in &self.vertices {
+            min = Vector3::new(min.x.min(v.pt.x), min.y.min(v.pt.y), min.z.min(v.pt.z));
+            max = Vector3::new(max.x.max(v.pt.x), max.y.max(v.pt.y), max.z.max(v.pt.z));
+        }
+        Some((min, max))
+    }
+
+    // Translate the mesh so that its bounding sphere fits in the camera field
+    // of view, and move the camera clipping planes around it.
+    pub fn fit_view(&mut self, cam: &mut Camera) {
+        let (min, max) = match self.bounds() {
+            Some(b) => b,
+            None => return,
+        };
+        let center = (min + max) / 2.;
+        let radius = self.vertices.iter()
+            .map(|v| (v.pt - center).length())
+            .fold(f32::EPSILON, f32::max);
+
+        let half_fov = cam.fov.to_radians() / 2.;
+        let half_fov = half_fov.min((half_fov.tan() * cam.aspect).atan());
+        let dist = radius / half_fov.sin();
+
+        // Rotate the center the same way the vertices will be.
+        let rot = Matrix4::rot(self.rot_x, self.rot_y, self.rot_z);
+        let (center, _) = &rot * center;
+        self.translation = Vector3::new(0., 0., -dist) - center;
+
+        cam.near = (dist - radius).max(dist / 1000.) * 0.5;
+        cam.far = dist + radius * 2.;
+    }
 }
@@ -1 +1,35 @@
+use crate::color::Color;
+use crate::light::Light;
+use crate::vector::Vector3;
 
+// Lighting environment of a render.
+pub struct Scene {
+    pub lights: Vec<Light>,
+    // Light received uniformly from every direction.
+    pub ambient: Color,
+    pub ambient_intensity: f32,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene {
+            lights: Vec::new(),
+            ambient: Color::WHITE,
+            ambient_intensity: 0.2,
+        }
+    }
+
+    // RGB ambient light.
+    pub fn ambient(&self) -> Vector3 {
+        self.ambient.to_vec() * self.ambient_intensity
+    }
+}
+
+impl Default for Scene {
+    // A single white directional light.
+    fn default() -> Scene {
+        let mut scene = Scene::new();
+        scene.lights.push(Light { intensity: 0.8, ..Light::new(1.,-1.,-1.) });
+        scene
+    }
+}
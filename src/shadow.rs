@@ -0,0 +1,173 @@
+use crate::color::Color;
+use crate::framebuffer::*;
+use crate::matrix::Matrix4;
+use crate::mesh::Mesh;
+use crate::renderer::transform_vertices;
+use crate::tiles::Rasterizer;
+use crate::varyings::Varyings;
+use crate::vector::Vector3;
+
+#[derive(Copy, Clone, Debug)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    // Width and height of the shadow map, in texels.
+    pub size: usize,
+    // Offset subtracted from the depth of the shaded points before the
+    // comparison, to avoid self-shadowing artifacts. The depth range of the
+    // map is [-1, 1]. Surfaces sloped towards the light get a larger bias,
+    // in proportion to the depth they span across the filtered texels.
+    pub bias: f32,
+    // Percentage-closer filtering: the shadow test is averaged over a square
+    // of (2 * radius + 1)^2 texels, 0 gives hard shadows.
+    pub pcf_radius: usize,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> ShadowSettings {
+        ShadowSettings {
+            enabled: true,
+            size: 1024,
+            bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+}
+
+// Depth of the scene as seen from a directional light.
+pub struct ShadowMap {
+    depth: Framebuffer,
+    // From world space to the clip space of the light.
+    mat: Matrix4,
+    bias: f32,
+    pcf_radius: usize,
+    // Width of a texel in world space, and its size in map depth.
+    texel: f32,
+    texel_depth: f32,
+}
+
+// Largest tangent of the angle between the normal and the light used to
+// scale the bias, reached at about 84 degrees.
+const MAX_SLOPE: f32 = 10.;
+
+impl ShadowMap {
+    // Render the depth of the mesh as seen by a directional light, with an
+    // orthographic projection fitted to the bounds of the mesh.
+    pub fn new(mesh: &Mesh, light_dir: Vector3, settings: &ShadowSettings, threads: usize) -> ShadowMap {
+        // The light looks down its -z axis.
+        let z = -light_dir;
+        let (x, y) = z.coord_system();
+        let light_view = Matrix4::from_basis(x, y, z);
+
+        let obj_mat = mesh.get_mat();
+        let view = light_view * obj_mat;
+
+        // Bounds of the mesh in the light frame, slightly padded so that the
+        // borders do not get clipped.
+        let mut min = Vector3::from_one(f32::MAX);
+        let mut max = Vector3::from_one(f32::MIN);
+        for v in &mesh.vertices {
+            let (p, _) = &view * v.pt;
+            min = Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let pad = (max - min) * 0.01 + Vector3::from_one(1e-4);
+        let (min, max) = (min - pad, max + pad);
+
+        let proj = Matrix4::orthographic(min.x, max.x, min.y, max.y, -max.z, -min.z);
+        let texel = (max.x - min.x).max(max.y - min.y) / settings.size as f32;
+        let texel_depth = texel * 2. / (max.z - min.z);
+        let mat = proj * light_view;
+
+        let mut depth = Framebuffer::new(settings.size, settings.size);
+        let clip_vertices = transform_vertices(mesh, &(mat * obj_mat), |_| Varyings::new());
+        // The triangles are all drawn once the rasterizer goes out of scope.
+        {
+            let mut r = Rasterizer::new(&mut depth, threads);
+            for tri in &mesh.faces {
+                r.draw_triangle([clip_vertices[tri.a], clip_vertices[tri.b], clip_vertices[tri.c]],
+                    Blend::Opaque, |_| Color::BLACK);
+            }
+        }
+
+        ShadowMap { depth, mat, bias: settings.bias, pcf_radius: settings.pcf_radius, texel, texel_depth }
+    }
+
+    // Fraction of the light reaching a point given in world space, from 0
+    // (fully in shadow) to 1. The unit normal of the surface and the
+    // direction towards the light set the bias.
+    pub fn visibility(&self, p: Vector3, normal: Vector3, l: Vector3) -> f32 {
+        let cos = normal.dot(&l).clamp(0., 1.);
+        let slope = ((1. - cos * cos).sqrt() / cos).min(MAX_SLOPE);
+        // The point is moved off the surface by up to two texels at grazing
+        // angles, where smooth normals can face the light while their
+        // triangles do not.
+        let (p, _) = &self.mat * (p + normal * (2. * self.texel * (1. - cos)));
+        let size = self.depth.width as f32;
+
+        // Same mapping as the viewport transform of the rasterizer.
+        let u = ((1. + p.x) * size / 2.).round() as i64;
+        let v = ((1. - p.y) * size / 2.).round() as i64;
+        let r = self.pcf_radius as i64;
+        // The surface can be this much deeper at the farthest sample.
+        let z = p.z - self.bias - self.texel_depth * slope * (r + 1) as f32;
+
+        let max = self.depth.width as i64 - 1;
+        let mut lit = 0;
+        for dy in -r ..= r {
+            for dx in -r ..= r {
+                let x = (u + dx).clamp(0, max) as usize;
+                let y = (v + dy).clamp(0, max) as usize;
+                if z <= self.depth.depth[self.depth.offset(x, y)] {
+                    lit += 1;
+                }
+            }
+        }
+
+        lit as f32 / ((2 * r + 1) * (2 * r + 1)) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::{Face, Vertex};
+
+    use super::*;
+
+    // Add a square with the given center and half sides.
+    fn push_square(mesh: &mut Mesh, center: Vector3, u: Vector3, v: Vector3) {
+        let start = mesh.vertices.len();
+        for p in &[center - u - v, center + u - v, center + u + v, center - u + v] {
+            mesh.vertices.push(Vertex { pt: *p, color: None, uv: None });
+        }
+        mesh.faces.push(Face::new(start, start + 1, start + 2));
+        mesh.faces.push(Face::new(start, start + 2, start + 3));
+    }
+
+    #[test]
+    fn shadows_without_acne() {
+        // A floor under a small square, and a wall at 80 degrees from the
+        // light, lit from above.
+        let mut mesh = Mesh::new();
+        let (x, y) = (Vector3::new(1., 0., 0.), Vector3::new(0., 1., 0.));
+        push_square(&mut mesh, Vector3::zero(), x * 4., y * 4.);
+        push_square(&mut mesh, Vector3::new(0., 0., 1.), x * 0.5, y * 0.5);
+        let angle = 80f32.to_radians();
+        let slope = Vector3::new(angle.cos(), 0., angle.sin());
+        push_square(&mut mesh, Vector3::new(2.5, 0., 2.), slope, y * 2.);
+        let wall_normal = Vector3::new(-angle.sin(), 0., angle.cos());
+
+        let up = Vector3::new(0., 0., 1.);
+        let settings = ShadowSettings { size: 256, ..ShadowSettings::default() };
+        let map = ShadowMap::new(&mesh, -up, &settings, 1);
+
+        assert_eq!(map.visibility(Vector3::zero(), up, up), 0.);
+        assert_eq!(map.visibility(Vector3::new(0.2, -0.3, 0.), up, up), 0.);
+        assert_eq!(map.visibility(Vector3::new(0., 0., 1.), up, up), 1.);
+        for i in 0 .. 20 {
+            let x = i as f32 * 0.19 - 1.8;
+            assert_eq!(map.visibility(Vector3::new(x, 2.5, 0.), up, up), 1., "floor at x = {}", x);
+            let wall = Vector3::new(2.5, 0., 2.) + slope * (x / 2.) + y * (x / 3.);
+            assert_eq!(map.visibility(wall, wall_normal, up), 1., "wall at {}", x);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::io::image;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    // Bilinear lookups in the two closest mipmap levels, blended together.
+    Trilinear,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        match s {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            "trilinear" => Ok(Filter::Trilinear),
+            _ => Err(format!("unknown texture filter [{}]", s)),
+        }
+    }
+}
+
+// What happens to texture coordinates outside of [0, 1].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Wrap, String> {
+        match s {
+            "repeat" => Ok(Wrap::Repeat),
+            "clamp" => Ok(Wrap::Clamp),
+            "mirror" => Ok(Wrap::Mirror),
+            _ => Err(format!("unknown wrap mode [{}]", s)),
+        }
+    }
+}
+
+impl Wrap {
+    // Map an integer texel coordinate into [0, size).
+    fn apply(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Clamp => i.clamp(0, size - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size { i } else { 2 * size - 1 - i }
+            },
+        };
+        i as usize
+    }
+}
+
+// One level of the mipmap chain, with RGBA components in [0, 1].
+struct Level {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 4]>,
+}
+
+impl Level {
+    // The wrap modes are given for x and y.
+    fn texel(&self, x: i64, y: i64, wrap: (Wrap, Wrap)) -> [f32; 4] {
+        self.texels[wrap.1.apply(y, self.height) * self.width + wrap.0.apply(x, self.width)]
+    }
+
+    fn nearest(&self, u: f32, v: f32, wrap: (Wrap, Wrap)) -> [f32; 4] {
+        let x = (u * self.width as f32).floor() as i64;
+        let y = (v * self.height as f32).floor() as i64;
+        self.texel(x, y, wrap)
+    }
+
+    fn bilinear(&self, u: f32, v: f32, wrap: (Wrap, Wrap)) -> [f32; 4] {
+        // Texel centers are at half-integer coordinates.
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = lerp(self.texel(x0, y0, wrap), self.texel(x0 + 1, y0, wrap), tx);
+        let bottom = lerp(self.texel(x0, y0 + 1, wrap), self.texel(x0 + 1, y0 + 1, wrap), tx);
+        lerp(top, bottom, ty)
+    }
+
+    // Next level of the chain, each texel averages a 2x2 block. Odd sizes
+    // repeat the last row or column.
+    fn downsample(&self) -> Level {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0 .. height {
+            for x in 0 .. width {
+                let mut sum = [0.; 4];
+                for (dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let t = self.texel((2 * x + dx) as i64, (2 * y + dy) as i64, (Wrap::Clamp, Wrap::Clamp));
+                    for (s, t) in sum.iter_mut().zip(&t) {
+                        *s += t / 4.;
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+        Level { width, height, texels }
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut out = a;
+    for (o, b) in out.iter_mut().zip(&b) {
+        *o += (b - *o) * t;
+    }
+    out
+}
+
+pub struct Texture {
+    // Full resolution image first, down to 1x1.
+    levels: Vec<Level>,
+    pub filter: Filter,
+    // Along u and v.
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+}
+
+impl Texture {
+    // Texture from RGBA texels given row by row from the top left corner,
+    // the top of the image being v = 0. Panics if the image is empty or if
+    // there are not width * height pixels, load checks the size instead.
+    pub fn new(width: usize, height: usize, pixels: &[Color]) -> Texture {
+        assert!(width > 0 && height > 0, "empty texture image [{}x{}]", width, height);
+        assert_eq!(pixels.len(), width * height, "wrong number of texels for a {}x{} texture", width, height);
+        let texels = pixels.iter()
+            .map(|c| [c.r as f32 / 255., c.g as f32 / 255., c.b as f32 / 255., c.a as f32 / 255.])
+            .collect();
+        let mut levels = vec!(Level { width, height, texels });
+        while levels.last().is_some_and(|l| l.width > 1 || l.height > 1) {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+        Texture { levels, filter: Filter::Trilinear, wrap_u: Wrap::Repeat, wrap_v: Wrap::Repeat }
+    }
+
+    // Load a PNG, PPM or TGA image.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Texture> {
+        let img = image::load(path)?;
+        if img.width == 0 || img.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty texture image"));
+        }
+        Ok(Texture::new(img.width, img.height, &img.pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    // Sample the texture at (u, v), given the screen space derivatives of the
+    // texture coordinates which select the mipmap level with the trilinear
+    // filter. Nearest and bilinear filtering always use the full resolution.
+    pub fn sample(&self, u: f32, v: f32, duv_dx: (f32, f32), duv_dy: (f32, f32)) -> Color {
+        let wrap = (self.wrap_u, self.wrap_v);
+        let t = match self.filter {
+            Filter::Nearest => self.levels[0].nearest(u, v, wrap),
+            Filter::Bilinear => self.levels[0].bilinear(u, v, wrap),
+            Filter::Trilinear => {
+                let lod = self.lod(duv_dx, duv_dy);
+                let level = lod.floor() as usize;
+                let fine = self.levels[level].bilinear(u, v, wrap);
+                if level + 1 < self.levels.len() {
+                    let coarse = self.levels[level + 1].bilinear(u, v, wrap);
+                    lerp(fine, coarse, lod.fract())
+                } else {
+                    fine
+                }
+            },
+        };
+        Color::from_f32(t[0], t[1], t[2], t[3])
+    }
+
+    // Mipmap level of detail, from the largest footprint of a pixel in texels.
+    fn lod(&self, duv_dx: (f32, f32), duv_dy: (f32, f32)) -> f32 {
+        let (w, h) = (self.width() as f32, self.height() as f32);
+        let dx = (duv_dx.0 * w).hypot(duv_dx.1 * h);
+        let dy = (duv_dy.0 * w).hypot(duv_dy.1 * h);
+        let lod = dx.max(dy).log2();
+        if lod.is_nan() {
+            0.
+        } else {
+            lod.clamp(0., (self.levels.len() - 1) as f32)
+        }
+    }
+}
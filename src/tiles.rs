@@ -0,0 +1,266 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::color::Color;
+use crate::framebuffer::*;
+use crate::clipping::ClipVertex;
+use crate::renderer::{ScreenVertex, Fragment, draw_triangle, screen_triangles, fill_tile};
+
+// Width and height of the tiles, in pixels, a multiple of the block size of
+// the rasterizer.
+pub const TILE_SIZE: usize = 64;
+
+// Number of threads used by default, one per core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+type Shader<'a> = Box<dyn Fn(&Fragment) -> Color + Sync + 'a>;
+
+// Triangle waiting to be rasterized, with the index of its shader.
+struct Triangle {
+    vertices: [ScreenVertex; 3],
+    blend: Blend,
+    shader: usize,
+}
+
+// Rectangle of the screen and the triangles overlapping it, in the order they
+// were drawn.
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    triangles: Vec<usize>,
+}
+
+// Where the render modes draw their triangles. With a single thread they are
+// rasterized right away. Otherwise they are queued and sorted into tiles of
+// the screen, which are rasterized in parallel when flushing, each into its
+// own copy of the pixels. Every pixel still gets its fragments in the order
+// the triangles were drawn, so the image is the same in both cases.
+pub struct Rasterizer<'a> {
+    fb: &'a mut Framebuffer,
+    threads: usize,
+    triangles: Vec<Triangle>,
+    shaders: Vec<Shader<'a>>,
+}
+
+impl<'a> Rasterizer<'a> {
+    pub fn new(fb: &'a mut Framebuffer, threads: usize) -> Rasterizer<'a> {
+        Rasterizer { fb, threads, triangles: vec!(), shaders: vec!() }
+    }
+
+    // Clip a triangle against the view frustum and rasterize what remains,
+    // see renderer::draw_triangle.
+    pub fn draw_triangle<F>(&mut self, tri: [ClipVertex; 3], blend: Blend, mut shader: F)
+        where F: Fn(&Fragment) -> Color + Sync + 'a {
+        if self.threads <= 1 {
+            draw_triangle(self.fb, tri, blend, &mut shader);
+            return;
+        }
+
+        let triangles = screen_triangles(self.fb, tri);
+        if triangles.is_empty() {
+            return;
+        }
+        let index = self.shaders.len();
+        self.shaders.push(Box::new(shader));
+        for vertices in triangles {
+            self.triangles.push(Triangle { vertices, blend, shader: index });
+        }
+    }
+
+    // The framebuffer, once the queued triangles are drawn.
+    pub fn framebuffer(&mut self) -> &mut Framebuffer {
+        self.flush();
+        self.fb
+    }
+
+    // Rasterize the queued triangles.
+    pub fn flush(&mut self) {
+        if self.triangles.is_empty() {
+            return;
+        }
+
+        let tiles = self.bin();
+        let next = AtomicUsize::new(0);
+        let (fb, triangles, shaders) = (&*self.fb, &self.triangles, &self.shaders);
+
+        // The workers take the tiles one after the other until there are none
+        // left, so that a few crowded tiles do not hold up one of them.
+        let drawn: Vec<(usize, Framebuffer)> = thread::scope(|s| {
+            let workers: Vec<_> = (0 .. self.threads.min(tiles.len()))
+                .map(|_| s.spawn(|| {
+                    let mut drawn = vec!();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let tile = match tiles.get(i) {
+                            Some(tile) => tile,
+                            None => return drawn,
+                        };
+                        drawn.push((i, draw_tile(fb, tile, triangles, shaders)));
+                    }
+                }))
+                .collect();
+            workers.into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect()
+        });
+
+        for (i, pixels) in drawn {
+            copy_back(self.fb, &tiles[i], &pixels);
+        }
+        self.triangles.clear();
+        self.shaders.clear();
+    }
+
+    // Sort the queued triangles into the tiles overlapped by their bounding
+    // box, empty tiles are left out.
+    fn bin(&self) -> Vec<Tile> {
+        let (width, height) = (self.fb.width, self.fb.height);
+        let columns = width.div_ceil(TILE_SIZE);
+        let rows = height.div_ceil(TILE_SIZE);
+        let mut tiles: Vec<Tile> = (0 .. rows * columns)
+            .map(|i| {
+                let (x, y) = (i % columns * TILE_SIZE, i / columns * TILE_SIZE);
+                Tile {
+                    x,
+                    y,
+                    width: TILE_SIZE.min(width - x),
+                    height: TILE_SIZE.min(height - y),
+                    triangles: vec!(),
+                }
+            })
+            .collect();
+
+        for (i, tri) in self.triangles.iter().enumerate() {
+            // Bounds of the pixels the rasterizer may draw, rounded outwards
+            // as the vertices are not snapped yet.
+            let [pa, pb, pc] = [tri.vertices[0].pos, tri.vertices[1].pos, tri.vertices[2].pos];
+            let x0 = pa.x.min(pb.x).min(pc.x).max(0.).floor();
+            let x1 = pa.x.max(pb.x).max(pc.x).min((width - 1) as f32).ceil();
+            let y0 = pa.y.min(pb.y).min(pc.y).max(0.).floor();
+            let y1 = pa.y.max(pb.y).max(pc.y).min((height - 1) as f32).ceil();
+            // Also false for NaN coordinates, which cover no pixel.
+            if !(x0 <= x1 && y0 <= y1) {
+                continue;
+            }
+
+            for row in y0 as usize / TILE_SIZE ..= y1 as usize / TILE_SIZE {
+                for column in x0 as usize / TILE_SIZE ..= x1 as usize / TILE_SIZE {
+                    tiles[row * columns + column].triangles.push(i);
+                }
+            }
+        }
+
+        tiles.retain(|t| !t.triangles.is_empty());
+        tiles
+    }
+}
+
+impl<'a> Drop for Rasterizer<'a> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// Rasterize the triangles of a tile into a copy of its pixels.
+fn draw_tile(fb: &Framebuffer, tile: &Tile, triangles: &[Triangle], shaders: &[Shader]) -> Framebuffer {
+    let mut pixels = copy_out(fb, tile);
+    for &i in &tile.triangles {
+        let tri = &triangles[i];
+        let shader = &shaders[tri.shader];
+        let [va, vb, vc] = &tri.vertices;
+        fill_tile(&mut pixels, (tile.x, tile.y), va, vb, vc, tri.blend, &mut |f| shader(f));
+    }
+    pixels
+}
+
+fn copy_out(fb: &Framebuffer, tile: &Tile) -> Framebuffer {
+    let mut pixels = Framebuffer::new(tile.width, tile.height);
+    let oit = !fb.accum.is_empty();
+    if oit {
+        pixels.clear_oit();
+    }
+    for y in 0 .. tile.height {
+        let from = fb.offset(tile.x, tile.y + y);
+        let to = pixels.offset(0, y);
+        let (src, dst) = (from .. from + tile.width, to .. to + tile.width);
+        pixels.color[dst.clone()].copy_from_slice(&fb.color[src.clone()]);
+        pixels.depth[dst.clone()].copy_from_slice(&fb.depth[src.clone()]);
+        if oit {
+            pixels.accum[dst.clone()].copy_from_slice(&fb.accum[src.clone()]);
+            pixels.revealage[dst].copy_from_slice(&fb.revealage[src]);
+        }
+    }
+    pixels
+}
+
+fn copy_back(fb: &mut Framebuffer, tile: &Tile, pixels: &Framebuffer) {
+    let oit = !fb.accum.is_empty();
+    for y in 0 .. tile.height {
+        let from = pixels.offset(0, y);
+        let to = fb.offset(tile.x, tile.y + y);
+        let (src, dst) = (from .. from + tile.width, to .. to + tile.width);
+        fb.color[dst.clone()].copy_from_slice(&pixels.color[src.clone()]);
+        fb.depth[dst.clone()].copy_from_slice(&pixels.depth[src.clone()]);
+        if oit {
+            fb.accum[dst.clone()].copy_from_slice(&pixels.accum[src.clone()]);
+            fb.revealage[dst].copy_from_slice(&pixels.revealage[src]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::path::Path;
+
+    use crate::camera::Camera;
+    use crate::io::off;
+    use crate::mesh::{Mesh, NormalWeighting, DEFAULT_CREASE_ANGLE_DEGREES};
+    use crate::renderer::*;
+    use crate::scene::Scene;
+    use super::*;
+
+    fn render_with(mesh: &Mesh, cam: &Camera, settings: &Settings) -> Framebuffer {
+        // Several tiles, the last row and column being partial.
+        let mut fb = Framebuffer::new(200, 150);
+        render(&mut fb, mesh, cam, &Scene::default(), settings);
+        fb
+    }
+
+    #[test]
+    fn same_image_as_single_thread() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("objects").join("klingon.off");
+        let mut mesh = off::import(&mut File::open(path).unwrap()).unwrap();
+        mesh.compute_normals(NormalWeighting::Angle, DEFAULT_CREASE_ANGLE_DEGREES.to_radians());
+        // Every other face is transparent, whose blending depends on the order
+        // of the fragments.
+        for (i, face) in mesh.faces.iter_mut().enumerate() {
+            if i % 2 == 1 {
+                face.color = Some(Color::rgba(200, 100, 50, 128));
+            }
+        }
+        let mut cam = Camera::new();
+        cam.aspect = 200. / 150.;
+        mesh.rot_x(-std::f32::consts::PI / 2.);
+        mesh.fit_view(&mut cam);
+
+        for mode in RenderMode::ALL.iter() {
+            for transparency in [Transparency::Sorted, Transparency::WeightedOit] {
+                let mut settings = Settings { mode: *mode, transparency, threads: 1, ..Settings::default() };
+                // The shadow maps are drawn by the rasterizer too.
+                settings.shadow.size = 256;
+                let single = render_with(&mesh, &cam, &settings);
+                settings.threads = 4;
+                let tiled = render_with(&mesh, &cam, &settings);
+
+                let name = format!("{:?} {:?}", mode, transparency);
+                assert!(single.color == tiled.color, "{}", name);
+                assert!(single.depth.iter().zip(&tiled.depth).all(|(a, b)| a.to_bits() == b.to_bits()), "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::ops::Sub;
+
+use crate::vector::Vector3;
+use crate::lanes::F32x4;
+
+pub const MAX_VARYINGS: usize = 16;
+
+// Per-vertex attributes (colors, normals, texture coordinates, ...) that are
+// interpolated across a triangle and handed to the fragment shader. They are
+// stored as a flat list of floats, the render mode decides of the layout.
+#[derive(Copy, Clone)]
+pub struct Varyings {
+    len: usize,
+    data: [f32; MAX_VARYINGS],
+}
+
+impl Varyings {
+    pub fn new() -> Varyings {
+        Varyings { len: 0, data: [0.; MAX_VARYINGS] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, v: f32) {
+        self.data[self.len] = v;
+        self.len += 1;
+    }
+
+    pub fn push_vec(&mut self, v: Vector3) {
+        self.push(v.x);
+        self.push(v.y);
+        self.push(v.z);
+    }
+
+    pub fn get(&self, index: usize) -> f32 {
+        self.data[index]
+    }
+
+    pub fn get_vec(&self, index: usize) -> Vector3 {
+        Vector3::new(self.data[index], self.data[index + 1], self.data[index + 2])
+    }
+
+    pub fn lerp(&self, other: &Varyings, t: f32) -> Varyings {
+        let mut out = *self;
+        for i in 0 .. self.len {
+            out.data[i] += (other.data[i] - self.data[i]) * t;
+        }
+        out
+    }
+
+    // Weighted sum of three sets of varyings, the weights are barycentric
+    // coordinates.
+    pub fn blend(a: &Varyings, b: &Varyings, c: &Varyings, wa: f32, wb: f32, wc: f32) -> Varyings {
+        let mut out = *a;
+        for i in 0 .. a.len {
+            out.data[i] = a.data[i] * wa + b.data[i] * wb + c.data[i] * wc;
+        }
+        out
+    }
+
+    // Blend for the four pixels of a quad at once, each lane of the weights
+    // giving the barycentric coordinates of a pixel. Same results as blend.
+    pub fn blend_quad(a: &Varyings, b: &Varyings, c: &Varyings, wa: F32x4, wb: F32x4, wc: F32x4) -> [Varyings; 4] {
+        let mut out = [*a; 4];
+        for i in 0 .. a.len {
+            let v = F32x4::splat(a.data[i]) * wa + F32x4::splat(b.data[i]) * wb + F32x4::splat(c.data[i]) * wc;
+            for (o, v) in out.iter_mut().zip(v.to_array().iter()) {
+                o.data[i] = *v;
+            }
+        }
+        out
+    }
+}
+
+impl Sub for Varyings {
+    type Output = Varyings;
+
+    fn sub(self, other: Varyings) -> Varyings {
+        let mut out = self;
+        for i in 0 .. self.len {
+            out.data[i] -= other.data[i];
+        }
+        out
+    }
+}
+
+impl Default for Varyings {
+    fn default() -> Varyings {
+        Varyings::new()
+    }
+}
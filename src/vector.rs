@@ -11,7 +11,7 @@ pub struct Vector3 {
 
 impl Vector3 {
     pub fn new(x: f32, y: f32, z: f32) -> Vector3 {
-        Vector3 { x:x, y:y, z:z }
+        Vector3 { x, y, z }
     }
 
     pub fn tuple(&self) -> (f32,f32,f32) {
@@ -47,7 +47,7 @@ impl Vector3 {
     }
 
     pub fn norm(&self) -> f32 {
-        (self.x * self.x + self.y * self.y + self.z * self.z)
+        self.x * self.x + self.y * self.y + self.z * self.z
     }
 
     pub fn normalize(&self) -> Vector3 {
@@ -63,6 +63,15 @@ impl Vector3 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 
+    // Component-wise product, used for colors.
+    pub fn mul_elem(&self, other: &Vector3) -> Vector3 {
+        Vector3 {
+            x: self.x * other.x,
+            y: self.y * other.y,
+            z: self.z * other.z,
+        }
+    }
+
     pub fn cross(&self, other: &Vector3) -> Vector3 {
         Vector3 {
             x: self.y * other.z - self.z * other.y,
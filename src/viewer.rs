@@ -0,0 +1,148 @@
+use sfml::graphics::{Color as SfColor, Texture, Sprite, RenderWindow, RenderTarget};
+use sfml::window::{Event, Key, mouse::Button, Style, mouse::Wheel};
+use sfml::system::Vector2i;
+
+use std::f32;
+
+use crate::renderer::*;
+use crate::mesh::Mesh;
+use crate::camera::Camera;
+use crate::vector::Vector3;
+use crate::scene::Scene;
+use crate::framebuffer::Framebuffer;
+use crate::io::image;
+
+// Open a window showing the mesh, which can be rotated with the mouse, until
+// it is closed. The mesh should already have normals.
+pub fn run(mut mesh: Mesh) {
+    let width: u32 = 800;
+    let height: u32 = 600;
+
+    // Rotate the mesh and translate it
+    mesh.translate(Vector3::new(0.,0.,-12.));
+    mesh.rot_x(-f32::consts::PI/2.);
+
+    let mut camera = Camera::new();
+    camera.aspect = width as f32 / height as f32;
+    let mut window = RenderWindow::new(
+        (width, height),
+        "Dot",
+        Style::CLOSE,
+        &Default::default(),
+    );
+    window.set_vertical_sync_enabled(true);
+    window.set_framerate_limit(60);
+    window.set_mouse_position(&Vector2i::new(width as i32 / 2, height as i32 / 2));
+
+    let scene = Scene::default();
+
+    let mut fb = Framebuffer::new(width as usize, height as usize);
+    let mut screen = Screen::new(width, height);
+
+    let mut settings = Settings::default();
+    let mut paused = false;
+    let mut rotate = false;
+    let mut prev_mp = Vector2i::new(0, 0);
+
+    loop {
+        while let Some(event) = window.poll_event() {
+            match event {
+                Event::Closed | Event::KeyPressed { code: Key::Escape, .. } =>
+                    return,
+                Event::KeyPressed { code: Key::Space, .. } =>
+                    paused = !paused,
+                Event::KeyPressed { code: Key::S, .. } =>
+                    settings.shadow.enabled = !settings.shadow.enabled,
+                Event::KeyPressed { code: Key::P, .. } =>
+                    screenshot(&fb),
+                Event::KeyPressed { code: Key::T, .. } =>
+                    settings.transparency = match settings.transparency {
+                        Transparency::Sorted => Transparency::WeightedOit,
+                        Transparency::WeightedOit => Transparency::Sorted,
+                    },
+                Event::MouseButtonPressed { button: Button::Left, x, y } => {
+                    prev_mp.x = x;
+                    prev_mp.y = y;
+                    rotate = true
+                },
+                Event::MouseButtonReleased { button: Button::Left, .. } =>
+                    rotate = false,
+                Event::MouseWheelScrolled {wheel: Wheel::Vertical, delta, ..} =>
+                    mesh.translate(Vector3::new(0.,0.,delta)),
+                Event::KeyPressed { code, .. } => {
+                    if let Some(m) = mode_for_key(code) {
+                        settings.mode = m
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if paused {
+            continue
+        }
+
+        if rotate {
+            let mp = window.mouse_position();
+            mesh.rot_y((mp.x - prev_mp.x) as f32 * 0.005);
+            mesh.rot_x((mp.y - prev_mp.y) as f32 * 0.005);
+            prev_mp = mp
+        }
+
+        render(&mut fb, &mesh, &camera, &scene, &settings);
+        screen.present(&mut window, &fb);
+        window.display();
+    }
+}
+
+fn mode_for_key(code: Key) -> Option<RenderMode> {
+    let index = match code {
+        Key::Num1 => 0,
+        Key::Num2 => 1,
+        Key::Num3 => 2,
+        Key::Num4 => 3,
+        Key::Num5 => 4,
+        Key::Num6 => 5,
+        Key::Num7 => 6,
+        _ => return None,
+    };
+    RenderMode::ALL.get(index).cloned()
+}
+
+
+// Save the last frame in the working directory.
+fn screenshot(fb: &Framebuffer) {
+    let path = "screenshot.png";
+    match image::save(path, fb) {
+        Ok(()) => println!("saved [{}]", path),
+        Err(e) => eprintln!("cannot save [{}]: {}", path, e),
+    }
+}
+
+// Copy of the framebuffer on the graphics card, drawn as a single sprite.
+pub struct Screen {
+    // RGBA bytes, row by row from the top left corner.
+    pixels: Vec<u8>,
+    texture: Texture,
+}
+
+impl Screen {
+    pub fn new(width: u32, height: u32) -> Screen {
+        Screen {
+            pixels: vec![0; width as usize * height as usize * 4],
+            texture: Texture::new(width, height).expect("cannot create the window texture"),
+        }
+    }
+
+    // Draw the content of the framebuffer in the window. The pixels are made
+    // opaque, the window shows the colors as they were rendered.
+    pub fn present(&mut self, window: &mut RenderWindow, fb: &Framebuffer) {
+        for (p, c) in self.pixels.chunks_exact_mut(4).zip(fb.color.iter()) {
+            p.copy_from_slice(&[c.r, c.g, c.b, 255]);
+        }
+        self.texture.update_from_pixels(&self.pixels, fb.width as u32, fb.height as u32, 0, 0);
+
+        window.clear(&SfColor::BLACK);
+        window.draw(&Sprite::with_texture(&self.texture));
+    }
+}